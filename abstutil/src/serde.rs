@@ -48,6 +48,18 @@ pub fn to_binary<T: Serialize>(obj: &T) -> Vec<u8> {
     bincode::serialize(obj).unwrap()
 }
 
+/// Hashes an object's bincoded bytes with 64-bit FNV-1a. Unlike `DefaultHasher`, the result
+/// doesn't change between platforms or Rust releases, so it's safe to save or compare across
+/// runs.
+pub fn stable_hash<T: Serialize>(obj: &T) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in to_binary(obj) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
 /// Serializes a BTreeMap as a list of tuples. Necessary when the keys are structs; see
 /// https://github.com/serde-rs/json/issues/402.
 pub fn serialize_btreemap<S: Serializer, K: Serialize, V: Serialize>(
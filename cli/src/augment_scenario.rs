@@ -1,5 +1,5 @@
 use rand::prelude::SliceRandom;
-use rand::{Rng, SeedableRng};
+use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 
 use abstutil::{prettyprint_usize, Timer};
@@ -20,7 +20,8 @@ pub fn run(
     let map = Map::load_synchronously(scenario.map_name.path(), &mut timer);
 
     if should_add_return_trips {
-        add_return_trips(&mut scenario, &mut rng);
+        let cnt = scenario.add_return_trips(&mut rng);
+        info!("Added return trips to {} people", prettyprint_usize(cnt));
     }
     if should_add_lunch_trips {
         add_lunch_trips(&mut scenario, &map, &mut rng, &mut timer);
@@ -29,32 +30,6 @@ pub fn run(
     scenario.save();
 }
 
-fn add_return_trips(scenario: &mut Scenario, rng: &mut XorShiftRng) {
-    let mut cnt = 0;
-    for person in &mut scenario.people {
-        if person.trips.len() != 1 {
-            continue;
-        }
-
-        // Assume a uniform distribution of 4-12 hour workday
-        let depart =
-            person.trips[0].depart + rand_duration(rng, Duration::hours(4), Duration::hours(12));
-        person.trips.push(IndividTrip::new(
-            depart,
-            TripPurpose::Home,
-            person.trips[0].destination,
-            person.trips[0].origin,
-            person.trips[0].mode,
-        ));
-        cnt += 1;
-    }
-    info!("Added return trips to {} people", prettyprint_usize(cnt));
-}
-
-fn rand_duration(rng: &mut XorShiftRng, low: Duration, high: Duration) -> Duration {
-    Duration::seconds(rng.gen_range(low.inner_seconds()..high.inner_seconds()))
-}
-
 fn add_lunch_trips(scenario: &mut Scenario, map: &Map, rng: &mut XorShiftRng, timer: &mut Timer) {
     // First let's build up a quadtree of lunch spots.
     timer.start("index lunch spots");
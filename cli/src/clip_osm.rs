@@ -1,103 +1,1131 @@
 use std::collections::HashSet;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 
-use anyhow::Result;
-use geo::prelude::Contains;
+use anyhow::{anyhow, bail, Result};
+use geo::prelude::{BoundingRect, Contains};
 use geo::{LineString, Point, Polygon};
 use osmio::obj_types::ArcOSMObj;
 use osmio::{Node, OSMObj, OSMObjBase, OSMObjectType, OSMReader, OSMWriter, Relation, Way};
+use serde::Serialize;
 
+use abstutil::{prettyprint_usize, Timer};
 use geom::LonLat;
 
-pub fn run(pbf_path: String, clip_path: String, out_path: String) -> Result<()> {
+/// Extra settings for clipping. The defaults just clip to the boundary.
+#[derive(Default)]
+pub struct Options {
+    /// Nodes, ways, and relations to drop from the output, even if they'd otherwise be kept.
+    /// Formatted like `way:123`. Ways using an excluded node are dropped too, so they don't
+    /// reference a missing node.
+    pub exclude_ids: Vec<String>,
+    /// Count references to nodes, ways, and relations that don't exist in the input at all, as
+    /// opposed to being outside the boundary. This is common when the input was already clipped.
+    pub report_missing: bool,
+    /// If specified, also write every reference to a missing object to this file. Implies
+    /// `report_missing`.
+    pub missing_list_path: Option<String>,
+    /// If specified, split the boundary's bounding box into a grid, formatted like `rows,cols`,
+    /// and write each non-empty tile to a separate file. The output path gets `_row_col` added
+    /// before the last extension, so it must have a file name. Objects spanning multiple tiles are
+    /// written to each of them.
+    pub tiles: Option<String>,
+    /// Just report how much would be written, without writing anything
+    pub dry_run: bool,
+    /// After writing, read the output back and check that every reference resolves. Fails if a
+    /// way refers to a missing node, unless `clip_mode` is `Loose`, which drops those nodes on
+    /// purpose. Doesn't work when writing to stdout.
+    pub validate: bool,
+    /// How to handle ways crossing the boundary
+    pub clip_mode: ClipMode,
+    /// If specified, remember which nodes are inside the boundary in this file, so clipping the
+    /// same input again can skip testing every node against the boundary. If the boundary
+    /// changes, the file is rebuilt. This assumes the input file hasn't changed.
+    pub node_cache: Option<String>,
+}
+
+/// How to handle ways that are partly inside the boundary
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClipMode {
+    /// Only keep ways entirely inside the boundary. With tiles, the way must be entirely inside
+    /// one tile.
+    Strict,
+    /// Keep ways with any node inside the boundary, but only the nodes inside. Ways crossing the
+    /// boundary will reference missing nodes.
+    Loose,
+    /// Keep ways with any node inside the boundary, along with all of their nodes, even those
+    /// outside. This is necessary to properly compute border intersections when importing.
+    Complete,
+}
+
+impl Default for ClipMode {
+    fn default() -> ClipMode {
+        ClipMode::Complete
+    }
+}
+
+impl std::str::FromStr for ClipMode {
+    type Err = anyhow::Error;
+
+    fn from_str(x: &str) -> Result<ClipMode> {
+        match x {
+            "strict" => Ok(ClipMode::Strict),
+            "loose" => Ok(ClipMode::Loose),
+            "complete" => Ok(ClipMode::Complete),
+            _ => bail!("{} isn't strict, loose, or complete", x),
+        }
+    }
+}
+
+pub fn run(pbf_path: String, clip_path: String, out_path: String, opts: Options) -> Result<()> {
     let boundary_pts = LonLat::read_osmosis_polygon(&clip_path)?;
     let raw_pts: Vec<(f64, f64)> = boundary_pts
         .into_iter()
         .map(|pt| (pt.x(), pt.y()))
         .collect();
     let boundary = Polygon::new(LineString::from(raw_pts), Vec::new());
-    clip(&pbf_path, &boundary, &out_path)
+    clip(
+        &pbf_path,
+        &Boundary::Polygon(boundary),
+        &clip_path,
+        &out_path,
+        &opts,
+    )
 }
 
-fn clip(pbf_path: &str, boundary: &Polygon<f64>, out_path: &str) -> Result<()> {
-    // TODO Maybe just have a single map with RcOSMObj. But then the order we write will be wrong.
-    let mut way_node_ids: HashSet<i64> = HashSet::new();
-    let mut way_ids: HashSet<i64> = HashSet::new();
-    let mut relation_ids: HashSet<i64> = HashSet::new();
-    {
-        // First Pass: accumulate the IDs we want to include in the output
-        let mut reader = osmio::pbf::PBFReader::new(BufReader::new(File::open(pbf_path)?));
-        let mut node_ids_within_boundary: HashSet<i64> = HashSet::new();
-        for obj in reader.objects() {
-            match obj.object_type() {
-                OSMObjectType::Node => {
-                    let node = obj.into_node().unwrap();
-                    if let Some(lat_lon) = node.lat_lon() {
-                        if boundary.contains(&to_pt(lat_lon)) {
-                            node_ids_within_boundary.insert(node.id());
-                        }
-                    }
-                }
-                OSMObjectType::Way => {
-                    // Assume all nodes appear before any way.
-                    let way = obj.into_way().unwrap();
-                    if way
-                        .nodes()
-                        .iter()
-                        .any(|id| node_ids_within_boundary.contains(id))
-                    {
-                        way_ids.insert(way.id());
+/// Clips to a rectangle instead of a polygon, which is much faster. The bounding box is
+/// formatted like `minlon,minlat,maxlon,maxlat`.
+pub fn run_bbox(pbf_path: String, bbox: String, out_path: String, opts: Options) -> Result<()> {
+    let coords = bbox
+        .split(',')
+        .map(|x| x.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if coords.len() != 4 {
+        bail!(
+            "{} should be formatted like minlon,minlat,maxlon,maxlat",
+            bbox
+        );
+    }
+    let (min_lon, min_lat, max_lon, max_lat) = (coords[0], coords[1], coords[2], coords[3]);
+    if min_lon >= max_lon || min_lat >= max_lat {
+        bail!("{} has the minimum and maximum swapped", bbox);
+    }
+    clip(
+        &pbf_path,
+        &Boundary::Rectangle {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        },
+        &format!("bbox {}", bbox),
+        &out_path,
+        &opts,
+    )
+}
 
-                        // To properly compute border nodes, we include all nodes of ways that are
-                        // at least partially in the boundary.
-                        way_node_ids.extend(way.nodes().iter().cloned());
-                    }
+enum Boundary {
+    Polygon(Polygon<f64>),
+    Rectangle {
+        min_lon: f64,
+        min_lat: f64,
+        max_lon: f64,
+        max_lat: f64,
+    },
+}
+
+impl Boundary {
+    /// Returns (min_lon, min_lat, max_lon, max_lat)
+    fn bounds(&self) -> Result<(f64, f64, f64, f64)> {
+        match self {
+            Boundary::Polygon(polygon) => {
+                let rect = polygon
+                    .bounding_rect()
+                    .ok_or_else(|| anyhow!("the boundary polygon is empty"))?;
+                Ok((rect.min().x, rect.min().y, rect.max().x, rect.max().y))
+            }
+            Boundary::Rectangle {
+                min_lon,
+                min_lat,
+                max_lon,
+                max_lat,
+            } => Ok((*min_lon, *min_lat, *max_lon, *max_lat)),
+        }
+    }
+
+    fn contains(&self, pt: &Point<f64>) -> bool {
+        match self {
+            Boundary::Polygon(polygon) => polygon.contains(pt),
+            Boundary::Rectangle {
+                min_lon,
+                min_lat,
+                max_lon,
+                max_lat,
+            } => (*min_lon..=*max_lon).contains(&pt.x()) && (*min_lat..=*max_lat).contains(&pt.y()),
+        }
+    }
+
+    /// A hash of the boundary's coordinates, to detect when it changes. This is saved in the
+    /// node cache, so it must be the same every run.
+    fn fingerprint(&self) -> u64 {
+        let coords: Vec<(f64, f64)> = match self {
+            Boundary::Polygon(polygon) => {
+                polygon.exterior().0.iter().map(|pt| (pt.x, pt.y)).collect()
+            }
+            Boundary::Rectangle {
+                min_lon,
+                min_lat,
+                max_lon,
+                max_lat,
+            } => vec![(*min_lon, *min_lat), (*max_lon, *max_lat)],
+        };
+        abstutil::stable_hash(&coords)
+    }
+}
+
+/// Reads the IDs of nodes inside a boundary, saved by a previous run. Returns `None` if the file
+/// doesn't exist yet or was made for a different boundary. The first line is the boundary's
+/// fingerprint, followed by one node ID per line.
+fn read_node_cache(path: &str, boundary: &Boundary) -> Result<Option<HashSet<i64>>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut lines = BufReader::new(file).lines();
+    let fingerprint = lines.next().transpose()?.unwrap_or_default();
+    if fingerprint != boundary.fingerprint().to_string() {
+        eprintln!(
+            "The boundary changed since {} was written; rebuilding it",
+            path
+        );
+        return Ok(None);
+    }
+    let mut ids = HashSet::new();
+    for line in lines {
+        ids.insert(line?.parse::<i64>()?);
+    }
+    Ok(Some(ids))
+}
+
+fn write_node_cache(path: &str, boundary: &Boundary, ids: &HashSet<i64>) -> Result<()> {
+    let mut f = BufWriter::new(File::create(path)?);
+    writeln!(f, "{}", boundary.fingerprint())?;
+    for id in ids {
+        writeln!(f, "{}", id)?;
+    }
+    eprintln!(
+        "Remembered {} nodes inside the boundary in {}",
+        prettyprint_usize(ids.len()),
+        path
+    );
+    Ok(())
+}
+
+#[derive(Default)]
+struct Excluded {
+    nodes: HashSet<i64>,
+    ways: HashSet<i64>,
+    relations: HashSet<i64>,
+}
+
+impl Excluded {
+    fn parse(ids: &[String]) -> Result<Excluded> {
+        let mut excluded = Excluded::default();
+        for raw in ids {
+            let (obj_type, id) = raw
+                .split_once(':')
+                .ok_or_else(|| anyhow!("{} should be formatted like way:123", raw))?;
+            let id: i64 = id.parse()?;
+            match obj_type {
+                "node" => {
+                    excluded.nodes.insert(id);
                 }
+                "way" => {
+                    excluded.ways.insert(id);
+                }
+                "relation" => {
+                    excluded.relations.insert(id);
+                }
+                _ => bail!("{} isn't a node, way, or relation", raw),
+            }
+        }
+        Ok(excluded)
+    }
+}
+
+/// Tracks references to objects that don't exist in the input at all
+struct MissingRefs {
+    all_node_ids: HashSet<i64>,
+    all_way_ids: HashSet<i64>,
+    all_relation_ids: HashSet<i64>,
+    // Relations that're members of other relations, as (parent, member). Checked in `finish`,
+    // since the member may appear later in the file.
+    relation_members: Vec<(i64, i64)>,
+    // (The object with the reference, the missing object)
+    refs: Vec<(String, String)>,
+}
+
+impl MissingRefs {
+    fn new() -> MissingRefs {
+        MissingRefs {
+            all_node_ids: HashSet::new(),
+            all_way_ids: HashSet::new(),
+            all_relation_ids: HashSet::new(),
+            relation_members: Vec::new(),
+            refs: Vec::new(),
+        }
+    }
+
+    fn add_node(&mut self, id: i64) {
+        self.all_node_ids.insert(id);
+    }
+
+    /// Assumes all nodes appear before any way.
+    fn check_way<W: Way>(&mut self, way: &W) {
+        self.all_way_ids.insert(way.id());
+        for id in way.nodes() {
+            if !self.all_node_ids.contains(id) {
+                self.refs
+                    .push((format!("way:{}", way.id()), format!("node:{}", id)));
+            }
+        }
+    }
+
+    fn check_relation<R: Relation>(&mut self, relation: &R) {
+        self.all_relation_ids.insert(relation.id());
+        for (obj_type, id, _) in relation.members() {
+            let (found, label) = match obj_type {
+                OSMObjectType::Node => (self.all_node_ids.contains(&id), "node"),
+                OSMObjectType::Way => (self.all_way_ids.contains(&id), "way"),
                 OSMObjectType::Relation => {
-                    let relation = obj.into_relation().unwrap();
-                    if relation.members().any(|(obj_type, id, _)| {
-                        (obj_type == OSMObjectType::Node && node_ids_within_boundary.contains(&id))
-                            || (obj_type == OSMObjectType::Way && way_ids.contains(&id))
-                            || (obj_type == OSMObjectType::Relation && relation_ids.contains(&id))
-                    }) {
-                        relation_ids.insert(relation.id());
+                    self.relation_members.push((relation.id(), id));
+                    continue;
+                }
+            };
+            if !found {
+                self.refs.push((
+                    format!("relation:{}", relation.id()),
+                    format!("{}:{}", label, id),
+                ));
+            }
+        }
+    }
+
+    /// Call after every object has been checked, to find relations referencing missing relations.
+    fn finish(&mut self) {
+        for (parent, id) in self.relation_members.drain(..) {
+            if !self.all_relation_ids.contains(&id) {
+                self.refs
+                    .push((format!("relation:{}", parent), format!("relation:{}", id)));
+            }
+        }
+    }
+
+    fn report(&self, list_path: Option<&str>) -> Result<()> {
+        // Use stderr, since the clipped output might be going to stdout
+        eprintln!(
+            "{} references to nodes, ways, and relations missing from the input",
+            prettyprint_usize(self.refs.len())
+        );
+        if let Some(path) = list_path {
+            let mut f = BufWriter::new(File::create(path)?);
+            for (from, to) in &self.refs {
+                writeln!(f, "{} references {}", from, to)?;
+            }
+            eprintln!("Wrote the list to {}", path);
+        }
+        Ok(())
+    }
+}
+
+/// Splits the boundary's bounding box into a grid of tiles
+struct Tiles {
+    rows: usize,
+    cols: usize,
+    min_lon: f64,
+    min_lat: f64,
+    tile_width: f64,
+    tile_height: f64,
+}
+
+impl Tiles {
+    /// Formatted like `rows,cols`
+    fn parse(raw: &str, boundary: &Boundary) -> Result<Tiles> {
+        let (rows, cols) = raw
+            .split_once(',')
+            .ok_or_else(|| anyhow!("{} should be formatted like rows,cols", raw))?;
+        let rows: usize = rows.trim().parse()?;
+        let cols: usize = cols.trim().parse()?;
+        if rows == 0 || cols == 0 {
+            bail!("{} must have at least one row and column", raw);
+        }
+        let (min_lon, min_lat, max_lon, max_lat) = boundary.bounds()?;
+        Ok(Tiles {
+            rows,
+            cols,
+            min_lon,
+            min_lat,
+            tile_width: (max_lon - min_lon) / (cols as f64),
+            tile_height: (max_lat - min_lat) / (rows as f64),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.rows * self.cols
+    }
+
+    /// Returns (min_lon, min_lat, max_lon, max_lat) of one tile
+    fn bounds(&self, idx: usize) -> (f64, f64, f64, f64) {
+        let min_lon = self.min_lon + self.tile_width * ((idx % self.cols) as f64);
+        let min_lat = self.min_lat + self.tile_height * ((idx / self.cols) as f64);
+        (
+            min_lon,
+            min_lat,
+            min_lon + self.tile_width,
+            min_lat + self.tile_height,
+        )
+    }
+
+    /// Assumes the point is within the boundary. Row 0 is the southernmost.
+    fn tile(&self, pt: &Point<f64>) -> usize {
+        // Points on the northern or eastern edge belong to the last row or column
+        let col =
+            (((pt.x() - self.min_lon) / self.tile_width).max(0.0) as usize).min(self.cols - 1);
+        let row =
+            (((pt.y() - self.min_lat) / self.tile_height).max(0.0) as usize).min(self.rows - 1);
+        row * self.cols + col
+    }
+
+    /// Inserts `_row_col` before the last extension of the output path, so `foo.osm.pbf` becomes
+    /// `foo.osm_0_0.pbf`. The output is always XML, whatever the extension says. `clip` checks
+    /// that the path has a file name.
+    fn path(&self, out_path: &str, idx: usize) -> String {
+        let path = std::path::Path::new(out_path);
+        let name = format!(
+            "{}_{}_{}",
+            path.file_stem().unwrap().to_string_lossy(),
+            idx / self.cols,
+            idx % self.cols
+        );
+        let mut path = path.with_file_name(name);
+        if let Some(ext) = std::path::Path::new(out_path).extension() {
+            path.set_extension(ext);
+        }
+        path.to_string_lossy().to_string()
+    }
+}
+
+/// How many nodes to buffer before testing them against the boundary. Each batch starts a new
+/// thread pool, so batches need to be large to make that worthwhile. At 24 bytes per node, this
+/// buffers about 50MB.
+const NODE_BATCH_SIZE: usize = 2_000_000;
+
+/// Buffers nodes from the input, so testing if they're inside the boundary can happen in
+/// parallel. This is the slowest part of clipping a large input to a polygon. The nodes inside
+/// are assigned to tiles in their original order, so the output doesn't depend on batching.
+struct NodeBatch {
+    batch_size: usize,
+    pending: Vec<(i64, Point<f64>)>,
+    /// The nodes inside the boundary, if they're already known from the node cache
+    cached: Option<HashSet<i64>>,
+    /// If there's no usable node cache yet, build one up
+    new_cache: Option<HashSet<i64>>,
+}
+
+impl NodeBatch {
+    fn new(batch_size: usize, cached: Option<HashSet<i64>>, build_cache: bool) -> NodeBatch {
+        NodeBatch {
+            batch_size,
+            pending: Vec::new(),
+            cached,
+            new_cache: if build_cache {
+                Some(HashSet::new())
+            } else {
+                None
+            },
+        }
+    }
+
+    fn push(
+        &mut self,
+        id: i64,
+        pt: Point<f64>,
+        boundary: &Boundary,
+        tiles: Option<&Tiles>,
+        kept: &mut [Kept],
+    ) {
+        self.pending.push((id, pt));
+        if self.pending.len() >= self.batch_size {
+            self.flush(boundary, tiles, kept);
+        }
+    }
+
+    /// Tests all pending nodes against the boundary, and assigns the ones inside to the right
+    /// tile.
+    fn flush(&mut self, boundary: &Boundary, tiles: Option<&Tiles>, kept: &mut [Kept]) {
+        // This is called for every way and relation, but only the first one has work to do
+        if self.pending.is_empty() {
+            return;
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let inside: Vec<(i64, Point<f64>)> = if let Some(ref ids) = self.cached {
+            pending
+                .into_iter()
+                .filter(|(id, _)| ids.contains(id))
+                .collect()
+        } else {
+            // One chunk per CPU
+            let num_cpus = num_cpus::get().max(1);
+            let chunk_size = (pending.len() + num_cpus - 1) / num_cpus;
+            let chunks: Vec<&[(i64, Point<f64>)]> = pending.chunks(chunk_size).collect();
+            // A throwaway timer doesn't print anything, so this is safe when writing to stdout
+            Timer::throwaway()
+                .parallelize("test nodes against the boundary", chunks, |chunk| {
+                    chunk
+                        .iter()
+                        .filter(|(_, pt)| boundary.contains(pt))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                })
+                .into_iter()
+                .flatten()
+                .collect()
+        };
+
+        for (id, pt) in inside {
+            if let Some(ref mut ids) = self.new_cache {
+                ids.insert(id);
+            }
+            let idx = tiles.map(|t| t.tile(&pt)).unwrap_or(0);
+            kept[idx].node_ids_within_boundary.insert(id);
+        }
+    }
+}
+
+/// The IDs of everything to write to one output file
+#[derive(Default)]
+struct Kept {
+    // Just used in the first pass
+    node_ids_within_boundary: HashSet<i64>,
+    // To properly compute border nodes, ClipMode::Complete includes all nodes of ways that are at
+    // least partially in the boundary.
+    way_node_ids: HashSet<i64>,
+    way_ids: HashSet<i64>,
+    relation_ids: HashSet<i64>,
+    // A rough guess at the size of the XML for ways and relations
+    estimated_bytes: usize,
+}
+
+impl Kept {
+    fn is_empty(&self) -> bool {
+        self.way_node_ids.is_empty() && self.relation_ids.is_empty()
+    }
+
+    /// Guesses how large the output file will be, based on the number of objects, node
+    /// references, relation members, and tags. Tags on nodes aren't counted.
+    fn estimated_size(&self) -> usize {
+        150 * self.way_node_ids.len() + self.estimated_bytes
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{} nodes, {} ways, {} relations, about {} bytes",
+            prettyprint_usize(self.way_node_ids.len()),
+            prettyprint_usize(self.way_ids.len()),
+            prettyprint_usize(self.relation_ids.len()),
+            prettyprint_usize(self.estimated_size())
+        )
+    }
+}
+
+/// The input and output paths can be `-` to use stdin and stdout.
+/// `boundary_source` describes where the boundary came from, for the metadata sidecar file.
+fn clip(
+    pbf_path: &str,
+    boundary: &Boundary,
+    boundary_source: &str,
+    out_path: &str,
+    opts: &Options,
+) -> Result<()> {
+    if opts.validate && out_path == "-" && !opts.dry_run {
+        bail!("Can't validate output written to stdout");
+    }
+    let tiles = match opts.tiles {
+        Some(ref raw) => {
+            if out_path == "-" {
+                bail!("Tiles can't be written to stdout");
+            }
+            if std::path::Path::new(out_path).file_stem().is_none() {
+                bail!(
+                    "Tiles need an output path with a file name, not {}",
+                    out_path
+                );
+            }
+            Some(Tiles::parse(raw, boundary)?)
+        }
+        None => None,
+    };
+
+    // We need to read the input twice, but stdin isn't seekable, so buffer it all in memory.
+    let stdin_buffer = if pbf_path == "-" {
+        let mut buffer = Vec::new();
+        std::io::stdin().read_to_end(&mut buffer)?;
+        Some(buffer)
+    } else {
+        None
+    };
+
+    let excluded = Excluded::parse(&opts.exclude_ids)?;
+    let mut missing = if opts.report_missing || opts.missing_list_path.is_some() {
+        Some(MissingRefs::new())
+    } else {
+        None
+    };
+
+    let cached_nodes = match opts.node_cache {
+        Some(ref path) => read_node_cache(path, boundary)?,
+        None => None,
+    };
+    // If there's no usable cache yet, build it during the first pass
+    let build_cache = opts.node_cache.is_some() && cached_nodes.is_none();
+    let mut nodes = NodeBatch::new(NODE_BATCH_SIZE, cached_nodes, build_cache);
+
+    let mut kept = {
+        let mut reader = osmio::pbf::PBFReader::new(open_input(pbf_path, &stdin_buffer)?);
+        find_kept(
+            reader.objects(),
+            boundary,
+            tiles.as_ref(),
+            opts,
+            &excluded,
+            &mut missing,
+            &mut nodes,
+        )
+    };
+
+    if let Some(mut missing) = missing {
+        missing.finish();
+        missing.report(opts.missing_list_path.as_deref())?;
+    }
+    if let (Some(path), Some(ids)) = (opts.node_cache.as_ref(), nodes.new_cache) {
+        write_node_cache(path, boundary, &ids)?;
+    }
+
+    for tile in &mut kept {
+        tile.node_ids_within_boundary.clear();
+    }
+
+    if opts.dry_run {
+        // Use stderr, like the other reports
+        match tiles {
+            Some(ref tiles) => {
+                for (idx, tile) in kept.iter().enumerate() {
+                    if !tile.is_empty() {
+                        eprintln!("{}: {}", tiles.path(out_path, idx), tile.describe());
                     }
                 }
             }
+            None => {
+                eprintln!("Would write {}", kept[0].describe());
+            }
         }
+        return Ok(());
+    }
+
+    let mut outputs = Vec::new();
+    let mut output_paths = Vec::new();
+    let created_unix_seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    for (idx, tile) in kept.into_iter().enumerate() {
+        let mut sidecar = Sidecar {
+            source: pbf_path.to_string(),
+            boundary: boundary_source.to_string(),
+            clip_mode: format!("{:?}", opts.clip_mode),
+            tile: None,
+            bbox: boundary.bounds()?,
+            nodes: tile.way_node_ids.len(),
+            ways: tile.way_ids.len(),
+            relations: tile.relation_ids.len(),
+            created_unix_seconds,
+        };
+        let output: Box<dyn Write> = match tiles {
+            Some(ref tiles) => {
+                if tile.is_empty() {
+                    continue;
+                }
+                let path = tiles.path(out_path, idx);
+                let file = File::create(&path)?;
+                sidecar.tile = Some((idx / tiles.cols, idx % tiles.cols));
+                sidecar.bbox = tiles.bounds(idx);
+                output_paths.push((path, sidecar));
+                Box::new(file)
+            }
+            // There's nowhere obvious to write the sidecar file
+            None if out_path == "-" => Box::new(std::io::stdout()),
+            None => {
+                output_paths.push((out_path.to_string(), sidecar));
+                Box::new(File::create(out_path)?)
+            }
+        };
+        outputs.push((tile, osmio::xml::XMLWriter::new(BufWriter::new(output))));
+    }
+    if tiles.is_some() {
+        eprintln!("Wrote {} non-empty tiles", outputs.len());
     }
 
-    let mut writer = osmio::xml::XMLWriter::new(BufWriter::new(File::create(out_path)?));
     // Second Pass: write the feature for each ID accumulated in the first pass
-    let mut reader = osmio::pbf::PBFReader::new(BufReader::new(File::open(pbf_path)?));
-    for obj in reader.objects() {
-        match &obj {
-            ArcOSMObj::Node(node) => {
-                if way_node_ids.contains(&node.id()) {
-                    writer.write_obj(&obj)?;
+    let mut reader = osmio::pbf::PBFReader::new(open_input(pbf_path, &stdin_buffer)?);
+    write_kept(reader.objects(), &mut outputs)?;
+
+    // Don't call write.close() -- it happens when writer gets dropped, and the implementation
+    // isn't idempotent.
+    drop(outputs);
+
+    for (path, sidecar) in output_paths {
+        // Describe how every extract was produced, since they often flow through a multi-stage
+        // import pipeline
+        std::fs::write(format!("{}.json", path), abstutil::to_json(&sidecar))?;
+        if opts.validate {
+            validate(&path, opts.clip_mode)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// First Pass: accumulate the IDs we want to include in each output. Read the input once, no
+/// matter how many tiles there are.
+fn find_kept<I: Iterator<Item = ArcOSMObj>>(
+    objects: I,
+    boundary: &Boundary,
+    tiles: Option<&Tiles>,
+    opts: &Options,
+    excluded: &Excluded,
+    missing: &mut Option<MissingRefs>,
+    nodes: &mut NodeBatch,
+) -> Vec<Kept> {
+    // Without tiles, everything goes to one output.
+    // TODO Maybe just have a single map with RcOSMObj. But then the order we write will be wrong.
+    let mut kept: Vec<Kept> = std::iter::repeat_with(Kept::default)
+        .take(tiles.map(|t| t.len()).unwrap_or(1))
+        .collect();
+    for obj in objects {
+        match obj.object_type() {
+            OSMObjectType::Node => {
+                let node = obj.into_node().unwrap();
+                if let Some(missing) = missing.as_mut() {
+                    missing.add_node(node.id());
+                }
+                if let Some(lat_lon) = node.lat_lon() {
+                    nodes.push(node.id(), to_pt(lat_lon), boundary, tiles, &mut kept);
                 }
             }
-            ArcOSMObj::Way(way) => {
-                if way_ids.contains(&way.id()) {
-                    writer.write_obj(&obj)?;
+            OSMObjectType::Way => {
+                // Assume all nodes appear before any way.
+                nodes.flush(boundary, tiles, &mut kept);
+
+                let way = obj.into_way().unwrap();
+                if let Some(missing) = missing.as_mut() {
+                    missing.check_way(&way);
+                }
+                if excluded.ways.contains(&way.id())
+                    || way.nodes().iter().any(|id| excluded.nodes.contains(id))
+                {
+                    continue;
+                }
+                // A way crossing tiles goes in each of them
+                for tile in &mut kept {
+                    let mut inside = way
+                        .nodes()
+                        .iter()
+                        .map(|id| tile.node_ids_within_boundary.contains(id));
+                    let keep = if opts.clip_mode == ClipMode::Strict {
+                        !way.nodes().is_empty() && inside.all(|x| x)
+                    } else {
+                        inside.any(|x| x)
+                    };
+                    if keep {
+                        tile.way_ids.insert(way.id());
+                        if opts.clip_mode == ClipMode::Loose {
+                            let within = &tile.node_ids_within_boundary;
+                            tile.way_node_ids
+                                .extend(way.nodes().iter().filter(|id| within.contains(*id)));
+                        } else {
+                            tile.way_node_ids.extend(way.nodes().iter().cloned());
+                        }
+                        tile.estimated_bytes +=
+                            150 + 25 * way.nodes().len() + 40 * way.tags().count();
+                    }
                 }
             }
-            ArcOSMObj::Relation(relation) => {
-                if relation_ids.contains(&relation.id()) {
-                    writer.write_obj(&obj)?;
+            OSMObjectType::Relation => {
+                nodes.flush(boundary, tiles, &mut kept);
+
+                let relation = obj.into_relation().unwrap();
+                if let Some(missing) = missing.as_mut() {
+                    missing.check_relation(&relation);
+                }
+                if excluded.relations.contains(&relation.id()) {
+                    continue;
+                }
+                for tile in &mut kept {
+                    if relation.members().any(|(obj_type, id, _)| {
+                        (obj_type == OSMObjectType::Node
+                            && tile.node_ids_within_boundary.contains(&id))
+                            || (obj_type == OSMObjectType::Way && tile.way_ids.contains(&id))
+                            || (obj_type == OSMObjectType::Relation
+                                && tile.relation_ids.contains(&id))
+                    }) {
+                        tile.relation_ids.insert(relation.id());
+                        tile.estimated_bytes +=
+                            150 + 50 * relation.members().count() + 40 * relation.tags().count();
+                    }
                 }
             }
         }
     }
+    // In case the input only has nodes
+    nodes.flush(boundary, tiles, &mut kept);
+    kept
+}
 
-    // Don't call write.close() -- it happens when writer gets dropped, and the implementation
-    // isn't idempotent.
+/// Metadata written alongside each output file, as `<output path>.json`
+#[derive(Serialize)]
+struct Sidecar {
+    source: String,
+    boundary: String,
+    clip_mode: String,
+    /// (row, column), if the output was split into tiles
+    tile: Option<(usize, usize)>,
+    /// (min_lon, min_lat, max_lon, max_lat) of the boundary, or just this tile
+    bbox: (f64, f64, f64, f64),
+    nodes: usize,
+    ways: usize,
+    relations: usize,
+    created_unix_seconds: u64,
+}
 
+/// Reads a clipped file back and checks that every reference resolves within it. Ways must have
+/// all of their nodes, or the importer will break. The loose clip mode drops nodes outside the
+/// boundary on purpose, so then broken ways are only reported. Relations crossing the boundary
+/// legitimately lose members outside of it, so those are only reported too.
+fn validate(path: &str, clip_mode: ClipMode) -> Result<()> {
+    let mut reader = osmio::xml::XMLReader::new(BufReader::new(File::open(path)?));
+    let missing = find_missing(reader.objects());
+
+    let (broken_ways, dangling_members): (Vec<_>, Vec<_>) = missing
+        .refs
+        .iter()
+        .partition(|(from, _)| from.starts_with("way:"));
+    eprintln!(
+        "{}: {} relation members outside the output",
+        path,
+        prettyprint_usize(dangling_members.len())
+    );
+    for (from, to) in dangling_members.iter().take(10) {
+        eprintln!("  {} references {}", from, to);
+    }
+    if broken_ways.is_empty() {
+        return Ok(());
+    }
+    if clip_mode == ClipMode::Loose {
+        eprintln!(
+            "{}: {} references from ways to nodes outside the output, as expected when clipping \
+             loosely",
+            path,
+            prettyprint_usize(broken_ways.len())
+        );
+        return Ok(());
+    }
+    for (from, to) in broken_ways.iter().take(10) {
+        eprintln!("  {} references {}", from, to);
+    }
+    bail!(
+        "{} has {} references from ways to missing nodes",
+        path,
+        prettyprint_usize(broken_ways.len())
+    );
+}
+
+/// Checks every reference between the objects. Assumes all nodes appear before any way.
+fn find_missing<I: Iterator<Item = ArcOSMObj>>(objects: I) -> MissingRefs {
+    let mut missing = MissingRefs::new();
+    for obj in objects {
+        match obj {
+            ArcOSMObj::Node(node) => missing.add_node(node.id()),
+            ArcOSMObj::Way(way) => missing.check_way(&way),
+            ArcOSMObj::Relation(relation) => missing.check_relation(&relation),
+        }
+    }
+    missing.finish();
+    missing
+}
+
+/// Writes the objects to keep exactly as they were read, so metadata like the version,
+/// timestamp, changeset, and user is preserved. This is necessary to upload changes to the
+/// clipped area back to OSM. Each object may go to multiple outputs.
+fn write_kept<I: Iterator<Item = ArcOSMObj>, W: Write>(
+    objects: I,
+    outputs: &mut [(Kept, osmio::xml::XMLWriter<W>)],
+) -> Result<()> {
+    for obj in objects {
+        for (kept, writer) in outputs.iter_mut() {
+            let keep = match &obj {
+                ArcOSMObj::Node(node) => kept.way_node_ids.contains(&node.id()),
+                ArcOSMObj::Way(way) => kept.way_ids.contains(&way.id()),
+                ArcOSMObj::Relation(relation) => kept.relation_ids.contains(&relation.id()),
+            };
+            if keep {
+                writer.write_obj(&obj)?;
+            }
+        }
+    }
     Ok(())
 }
 
+fn open_input<'a>(pbf_path: &str, stdin_buffer: &'a Option<Vec<u8>>) -> Result<Box<dyn Read + 'a>> {
+    match stdin_buffer {
+        Some(buffer) => Ok(Box::new(buffer.as_slice())),
+        None => Ok(Box::new(BufReader::new(File::open(pbf_path)?))),
+    }
+}
+
 fn to_pt(pair: (osmio::Lat, osmio::Lon)) -> Point<f64> {
     // Note our polygon uses (lon, lat)
     (pair.1.into(), pair.0.into()).into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_survives_clip() -> Result<()> {
+        let input = r#"<?xml version='1.0' encoding='UTF-8'?>
+<osm version="0.6">
+    <node id="1" version="3" changeset="42" uid="7" user="mapper" timestamp="2021-01-02T03:04:05Z" lat="47.6" lon="-122.3"/>
+    <node id="2" version="1" changeset="43" uid="8" user="other" timestamp="2021-02-03T04:05:06Z" lat="47.7" lon="-122.3"/>
+    <node id="3" version="2" changeset="45" uid="8" user="other" timestamp="2021-02-03T04:05:06Z" lat="48.0" lon="-122.3"/>
+    <way id="10" version="5" changeset="44" uid="7" user="mapper" timestamp="2021-03-04T05:06:07Z">
+        <nd ref="1"/>
+        <nd ref="2"/>
+        <tag k="highway" v="residential"/>
+    </way>
+</osm>"#;
+
+        let path = std::env::temp_dir().join("clip_osm_metadata_test.osm");
+        {
+            let kept = Kept {
+                way_node_ids: vec![1, 2].into_iter().collect(),
+                way_ids: vec![10].into_iter().collect(),
+                ..Default::default()
+            };
+            let writer = osmio::xml::XMLWriter::new(File::create(&path)?);
+            let mut reader = osmio::xml::XMLReader::new(input.as_bytes());
+            write_kept(reader.objects(), &mut [(kept, writer)])?;
+        }
+
+        let expected: Vec<_> = osmio::xml::XMLReader::new(input.as_bytes())
+            .objects()
+            .filter(|obj| obj.id() != 3)
+            .map(|obj| metadata(&obj))
+            .collect();
+        let actual: Vec<_> = osmio::xml::XMLReader::new(BufReader::new(File::open(&path)?))
+            .objects()
+            .map(|obj| metadata(&obj))
+            .collect();
+        std::fs::remove_file(&path)?;
+        assert_eq!(expected, actual);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tiles() -> Result<()> {
+        let boundary = Boundary::Rectangle {
+            min_lon: 0.0,
+            min_lat: 0.0,
+            max_lon: 4.0,
+            max_lat: 2.0,
+        };
+        let tiles = Tiles::parse("2,4", &boundary)?;
+        assert_eq!(0, tiles.tile(&(0.5, 0.5).into()));
+        assert_eq!(6, tiles.tile(&(2.5, 1.5).into()));
+        // The far edges belong to the last row and column
+        assert_eq!(7, tiles.tile(&(4.0, 2.0).into()));
+        assert_eq!("out/clipped_1_2.osm", tiles.path("out/clipped.osm", 6));
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_batch_straddles_ways() -> Result<()> {
+        let input = r#"<?xml version='1.0' encoding='UTF-8'?>
+<osm version="0.6">
+    <node id="1" lat="0.1" lon="0.1"/>
+    <node id="2" lat="0.2" lon="0.2"/>
+    <node id="3" lat="0.3" lon="0.3"/>
+    <node id="4" lat="5.0" lon="5.0"/>
+    <way id="10">
+        <nd ref="3"/>
+        <nd ref="4"/>
+    </way>
+    <way id="11">
+        <nd ref="4"/>
+    </way>
+</osm>"#;
+        let boundary = Boundary::Rectangle {
+            min_lon: 0.0,
+            min_lat: 0.0,
+            max_lon: 1.0,
+            max_lat: 1.0,
+        };
+
+        // The first two nodes fill a batch. The last two are still pending when the first way
+        // shows up.
+        let mut nodes = NodeBatch::new(2, None, true);
+        let kept = find_kept(
+            osmio::xml::XMLReader::new(input.as_bytes()).objects(),
+            &boundary,
+            None,
+            &Options::default(),
+            &Excluded::default(),
+            &mut None,
+            &mut nodes,
+        );
+
+        assert!(nodes.pending.is_empty());
+        let expected_nodes: HashSet<i64> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(expected_nodes, kept[0].node_ids_within_boundary);
+        assert_eq!(Some(expected_nodes), nodes.new_cache);
+        let expected_ways: HashSet<i64> = vec![10].into_iter().collect();
+        assert_eq!(expected_ways, kept[0].way_ids);
+        Ok(())
+    }
+
+    #[test]
+    fn test_excluded_node_drops_way() -> Result<()> {
+        let input = r#"<?xml version='1.0' encoding='UTF-8'?>
+<osm version="0.6">
+    <node id="1" lat="0.1" lon="0.1"/>
+    <node id="2" lat="0.2" lon="0.2"/>
+    <node id="3" lat="0.3" lon="0.3"/>
+    <way id="10">
+        <nd ref="1"/>
+        <nd ref="2"/>
+    </way>
+    <way id="11">
+        <nd ref="2"/>
+        <nd ref="3"/>
+    </way>
+</osm>"#;
+        let boundary = Boundary::Rectangle {
+            min_lon: 0.0,
+            min_lat: 0.0,
+            max_lon: 1.0,
+            max_lat: 1.0,
+        };
+        let excluded = Excluded::parse(&["node:3".to_string()])?;
+
+        let mut kept = find_kept(
+            osmio::xml::XMLReader::new(input.as_bytes()).objects(),
+            &boundary,
+            None,
+            &Options::default(),
+            &excluded,
+            &mut None,
+            &mut NodeBatch::new(NODE_BATCH_SIZE, None, false),
+        );
+        let kept = kept.remove(0);
+        let expected_ways: HashSet<i64> = vec![10].into_iter().collect();
+        assert_eq!(expected_ways, kept.way_ids);
+
+        let path = std::env::temp_dir().join("clip_osm_exclude_test.osm");
+        {
+            let writer = osmio::xml::XMLWriter::new(File::create(&path)?);
+            let mut reader = osmio::xml::XMLReader::new(input.as_bytes());
+            write_kept(reader.objects(), &mut [(kept, writer)])?;
+        }
+        let result = validate(path.to_str().unwrap(), ClipMode::Complete);
+        std::fs::remove_file(&path)?;
+        result
+    }
+
+    #[test]
+    fn test_tiles_need_output_file_name() {
+        let boundary = Boundary::Rectangle {
+            min_lon: 0.0,
+            min_lat: 0.0,
+            max_lon: 1.0,
+            max_lat: 1.0,
+        };
+        let opts = Options {
+            tiles: Some("2,2".to_string()),
+            ..Default::default()
+        };
+        for out_path in ["..", "out/.."] {
+            let err = clip("missing.osm.pbf", &boundary, "test", out_path, &opts).unwrap_err();
+            assert!(err.to_string().contains("file name"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_validate_checks_relations_and_loose_ways() -> Result<()> {
+        // Way 10 lost node 2, like when clipping loosely. Relation 20 references relation 21,
+        // which appears later, and relation 99, which is gone.
+        let input = r#"<?xml version='1.0' encoding='UTF-8'?>
+<osm version="0.6">
+    <node id="1" lat="0.1" lon="0.1"/>
+    <way id="10">
+        <nd ref="1"/>
+        <nd ref="2"/>
+    </way>
+    <relation id="20">
+        <member type="relation" ref="21" role=""/>
+        <member type="relation" ref="99" role=""/>
+    </relation>
+    <relation id="21">
+        <member type="way" ref="10" role=""/>
+    </relation>
+</osm>"#;
+
+        let missing = find_missing(osmio::xml::XMLReader::new(input.as_bytes()).objects());
+        assert_eq!(
+            missing.refs,
+            vec![
+                ("way:10".to_string(), "node:2".to_string()),
+                ("relation:20".to_string(), "relation:99".to_string()),
+            ]
+        );
+
+        let path = std::env::temp_dir().join("clip_osm_validate_test.osm");
+        std::fs::write(&path, input)?;
+        let loose = validate(path.to_str().unwrap(), ClipMode::Loose);
+        let complete = validate(path.to_str().unwrap(), ClipMode::Complete);
+        std::fs::remove_file(&path)?;
+        loose?;
+        assert!(complete.is_err());
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn metadata(
+        obj: &ArcOSMObj,
+    ) -> (
+        i64,
+        Option<u32>,
+        Option<u32>,
+        Option<u32>,
+        Option<String>,
+        Option<String>,
+    ) {
+        (
+            obj.id(),
+            obj.version(),
+            obj.changeset_id(),
+            obj.uid(),
+            obj.user().map(|user| user.to_string()),
+            obj.timestamp().as_ref().map(|t| t.to_string()),
+        )
+    }
+}
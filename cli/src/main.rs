@@ -46,8 +46,8 @@ enum Command {
         /// in; be careful about running this on the correct input.
         #[structopt(long)]
         input_scenario: String,
-        /// For people with only a single trip, add a return trip back home sometime 4-12 hours
-        /// later
+        /// For people whose last trip doesn't end where they started, add a return trip back home
+        /// sometime 4-12 hours later
         #[structopt(long)]
         add_return_trips: bool,
         /// Before a person's final trp home, insert a round-trip to a nearby cafe or restaurant
@@ -60,15 +60,53 @@ enum Command {
     /// Clips an OSM file to a boundary. This is a simple Rust port of `osmconvert large_map.osm
     /// -B=clipping.poly --complete-ways -o=smaller_map.osm`.
     ClipOSM {
-        /// The path to the input .osm.pbf file
+        /// The path to the input .osm.pbf file, or `-` to read from stdin
         #[structopt(long)]
         pbf_path: String,
         /// The path to an Osmosis boundary polygon
         #[structopt(long)]
-        clip_path: String,
-        /// The path to write the XML results
+        clip_path: Option<String>,
+        /// Instead of a boundary polygon, clip to a rectangle, formatted like
+        /// `minlon,minlat,maxlon,maxlat`. This is much faster.
+        #[structopt(long)]
+        bbox: Option<String>,
+        /// The path to write the XML results, or `-` to write to stdout. Unless writing to
+        /// stdout, a JSON file describing the extract is also written to this path plus `.json`.
         #[structopt(long)]
         out_path: String,
+        /// Drop an object from the output, even if it'd otherwise be kept. Formatted like
+        /// `way:123`; `node` and `relation` also work. Can be repeated.
+        #[structopt(long)]
+        exclude_id: Vec<String>,
+        /// Count references to nodes, ways, and relations that're missing from the input entirely,
+        /// as opposed to being outside the boundary
+        #[structopt(long)]
+        report_missing: bool,
+        /// Write every reference to a missing node, way, or relation to this file
+        #[structopt(long)]
+        missing_list: Option<String>,
+        /// Split the output into a grid of tiles, formatted like `rows,cols`. Each non-empty tile
+        /// is written to a separate file, named by adding `_row_col` to the output path.
+        #[structopt(long)]
+        tiles: Option<String>,
+        /// Don't write anything; just report how many objects would be written and roughly how
+        /// large the output would be
+        #[structopt(long)]
+        dry_run: bool,
+        /// After writing, read the output back and check that every way's nodes and relation's
+        /// members are present. Fails if a way is broken, except with `--clip-mode loose`.
+        #[structopt(long)]
+        validate: bool,
+        /// How to handle ways crossing the boundary. `strict` only keeps ways entirely inside.
+        /// `loose` keeps ways partly inside, but drops their nodes outside. `complete` keeps ways
+        /// partly inside with all of their nodes.
+        #[structopt(long, default_value = "complete")]
+        clip_mode: clip_osm::ClipMode,
+        /// Remember which nodes are inside the boundary in this file. When clipping the same input
+        /// again, this skips testing every node against the boundary. The file is rebuilt if the
+        /// boundary changes.
+        #[structopt(long)]
+        node_cache: Option<String>,
     },
     /// Reads a GeoJSON file, extracts a polygon from every feature, and writes numbered files in
     /// the https://wiki.openstreetmap.org/wiki/Osmosis/Polygon_Filter_File_Format format as
@@ -188,8 +226,33 @@ async fn main() -> Result<()> {
         Command::ClipOSM {
             pbf_path,
             clip_path,
+            bbox,
             out_path,
-        } => clip_osm::run(pbf_path, clip_path, out_path)?,
+            exclude_id,
+            report_missing,
+            missing_list,
+            tiles,
+            dry_run,
+            validate,
+            clip_mode,
+            node_cache,
+        } => {
+            let opts = clip_osm::Options {
+                exclude_ids: exclude_id,
+                report_missing,
+                missing_list_path: missing_list,
+                tiles,
+                dry_run,
+                validate,
+                clip_mode,
+                node_cache,
+            };
+            match (clip_path, bbox) {
+                (Some(clip_path), None) => clip_osm::run(pbf_path, clip_path, out_path, opts)?,
+                (None, Some(bbox)) => clip_osm::run_bbox(pbf_path, bbox, out_path, opts)?,
+                _ => anyhow::bail!("Specify exactly one of --clip-path or --bbox"),
+            }
+        }
         Command::GeoJSONToOsmosis { input } => geojson_to_osmosis::run(input)?,
         Command::ImportGrid2Demand { input, map } => import_grid2demand::run(input, map)?,
         Command::ImportScenario {
@@ -72,7 +72,12 @@ pub async fn run(
 
         // Clip it
         println!("Clipping osm.pbf file to your boundary");
-        crate::clip_osm::run(pbf, "boundary0.poly".to_string(), osm.clone())?;
+        crate::clip_osm::run(
+            pbf,
+            "boundary0.poly".to_string(),
+            osm.clone(),
+            crate::clip_osm::Options::default(),
+        )?;
     }
 
     // Import!
@@ -361,6 +361,8 @@ pub fn spawn_agents_around(i: IntersectionID, app: &mut App) {
                 };
                 scenario.people.push(PersonSpec {
                     orig_id: None,
+                    wheelchair: false,
+                    household: None,
                     trips: vec![IndividTrip::new(
                         app.primary.sim.time(),
                         TripPurpose::Shopping,
@@ -377,6 +379,8 @@ pub fn spawn_agents_around(i: IntersectionID, app: &mut App) {
             for _ in 0..5 {
                 scenario.people.push(PersonSpec {
                     orig_id: None,
+                    wheelchair: false,
+                    household: None,
                     trips: vec![IndividTrip::new(
                         app.primary.sim.time(),
                         TripPurpose::Shopping,
@@ -102,6 +102,8 @@ impl State<App> for AgentSpawner {
                     for _ in 0..self.panel.spinner("number") {
                         scenario.people.push(PersonSpec {
                             orig_id: None,
+                            wheelchair: false,
+                            household: None,
                             trips: vec![IndividTrip::new(
                                 app.primary.sim.time(),
                                 TripPurpose::Shopping,
@@ -1073,6 +1073,8 @@ impl TutorialState {
                     let mut scenario = Scenario::empty(map, "prank");
                     scenario.people.push(PersonSpec {
                         orig_id: None,
+                        wheelchair: false,
+                        household: None,
                         trips: vec![IndividTrip::new(
                             Time::START_OF_DAY,
                             TripPurpose::Shopping,
@@ -1088,6 +1090,8 @@ impl TutorialState {
                     for _ in 0..map.get_b(goal_bldg).num_parking_spots() {
                         scenario.people.push(PersonSpec {
                             orig_id: None,
+                            wheelchair: false,
+                            household: None,
                             trips: vec![IndividTrip::new(
                                 Time::START_OF_DAY,
                                 TripPurpose::Shopping,
@@ -545,9 +545,7 @@ impl State<App> for SandboxLoader {
                         app.primary.scenario = Some(scenario.clone());
 
                         if let GameplayMode::PlayScenario(_, _, ref modifiers) = self.mode {
-                            for m in modifiers {
-                                scenario = m.apply(&app.primary.map, scenario);
-                            }
+                            scenario.apply_modifiers(&app.primary.map, modifiers);
                         }
 
                         scenario.instantiate(
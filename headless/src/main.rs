@@ -480,9 +480,7 @@ impl LoadSim {
             map.recalculate_pathfinding_after_edits(timer);
         }
 
-        for m in &self.modifiers {
-            scenario = m.apply(&map, scenario);
-        }
+        scenario.apply_modifiers(&map, &self.modifiers);
 
         let mut rng = XorShiftRng::seed_from_u64(self.rng_seed);
         let mut sim = Sim::new(&map, self.opts.clone());
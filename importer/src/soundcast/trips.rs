@@ -282,6 +282,8 @@ pub fn make_scenario(
 
         people.push(PersonSpec {
             orig_id: Some(orig_id),
+            wheelchair: false,
+            household: None,
             trips,
         });
     }
@@ -296,6 +298,7 @@ pub fn make_scenario(
         map_name: map.get_name().clone(),
         people,
         only_seed_buses: None,
+        rng_seed: None,
     }
     .remove_weird_schedules()
 }
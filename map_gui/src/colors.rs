@@ -9,6 +9,7 @@ use serde::{Deserialize, Serialize};
 
 use map_model::osm::RoadRank;
 use map_model::LaneType;
+use sim::{PersonID, PersonSpec};
 use widgetry::{Choice, Color, EventCtx, Fill, Style, Texture};
 
 use crate::tools::{loading_tips, ColorScale};
@@ -370,6 +371,19 @@ impl ColorScheme {
         modulo_color(&self.agent_colors, idx)
     }
 
+    /// A color for one person from a scenario, the same across runs and in different views.
+    pub fn person_color(&self, person: &PersonSpec, id: PersonID) -> Color {
+        let hash = person.stable_hash(id);
+        let base = modulo_color(&self.agent_colors, (hash % 1000) as usize);
+        // Vary the shade, so more people are distinguishable than there are base colors
+        let pct = ((hash >> 32) % 100) as f64 / 100.0 * 0.6 - 0.3;
+        if pct < 0.0 {
+            base.shade(-pct)
+        } else {
+            base.tint(pct)
+        }
+    }
+
     pub fn unzoomed_road_surface(&self, rank: RoadRank) -> Color {
         match rank {
             RoadRank::Highway => self.unzoomed_highway,
@@ -183,6 +183,8 @@ impl PersonFactory {
 
         let mut output = PersonSpec {
             orig_id: None,
+            wheelchair: false,
+            household: None,
             trips: Vec::new(),
         };
 
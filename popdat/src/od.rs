@@ -96,6 +96,8 @@ pub fn disaggregate(
                 let return_home_time = goto_work_time + opts.work_duration.sample(rng);
                 people.push(PersonSpec {
                     orig_id: None,
+                    wheelchair: false,
+                    household: None,
                     trips: vec![
                         IndividTrip::new(
                             goto_work_time,
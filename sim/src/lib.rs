@@ -37,9 +37,10 @@ pub use self::analytics::{Analytics, Problem, SlidingWindow, TripPhase};
 pub(crate) use self::events::Event;
 pub use self::events::{AlertLocation, TripPhaseType};
 pub use self::make::{
-    fork_rng, BorderSpawnOverTime, ExternalPerson, ExternalTrip, ExternalTripEndpoint, IndividTrip,
-    MapBorders, PersonSpec, Scenario, ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime,
-    TripEndpoint, TripPurpose,
+    fork_rng, AggregateTargets, BorderSpawnOverTime, ExternalPerson, ExternalTrip,
+    ExternalTripEndpoint, IndividTrip, InstantiateOptions, MapBorders, PersonSpec, Scenario,
+    ScenarioGenerator, ScenarioModifier, SimFlags, SpawnFailure, SpawnFailureKind, SpawnOverTime,
+    TripEndpoint, TripLengthStats, TripPurpose,
 };
 pub(crate) use self::make::{StartTripArgs, TripSpec};
 pub(crate) use self::mechanics::{
@@ -407,6 +408,24 @@ impl DrivingGoal {
             }
         }
     }
+
+    /// A human-readable description of where this goal is, for logging.
+    pub fn describe(&self, map: &Map) -> String {
+        match self {
+            DrivingGoal::ParkNear(b) => {
+                let bldg = map.get_b(*b);
+                match bldg.name {
+                    Some(ref name) => {
+                        format!("park near {} ({}, {})", b, name.get(None), bldg.address)
+                    }
+                    None => format!("park near {} ({})", b, bldg.address),
+                }
+            }
+            DrivingGoal::Border(i, last_lane) => {
+                format!("exit the map at border {} via {}", i, last_lane)
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
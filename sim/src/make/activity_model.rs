@@ -270,6 +270,8 @@ fn create_prole(
 
     Ok(PersonSpec {
         orig_id: None,
+        wheelchair: false,
+        household: None,
         trips: vec![
             IndividTrip::new(depart_am, TripPurpose::Work, home, work, mode),
             IndividTrip::new(depart_pm, TripPurpose::Home, work, home, mode),
@@ -74,6 +74,8 @@ impl ExternalPerson {
         for person in input {
             let mut spec = PersonSpec {
                 orig_id: None,
+                wheelchair: false,
+                household: None,
                 trips: Vec::new(),
             };
             for trip in person.trips {
@@ -163,6 +163,8 @@ impl SpawnOverTime {
         };
         scenario.people.push(PersonSpec {
             orig_id: None,
+            wheelchair: false,
+            household: None,
             trips: vec![IndividTrip::new(
                 depart,
                 TripPurpose::Shopping,
@@ -181,6 +183,8 @@ impl BorderSpawnOverTime {
         let depart = rand_time(rng, self.start_time, self.stop_time);
         scenario.people.push(PersonSpec {
             orig_id: None,
+            wheelchair: false,
+            household: None,
             trips: vec![IndividTrip::new(
                 depart,
                 TripPurpose::Shopping,
@@ -8,8 +8,11 @@ pub use self::external::{ExternalPerson, ExternalTrip, ExternalTripEndpoint, Map
 pub use self::generator::{BorderSpawnOverTime, ScenarioGenerator, SpawnOverTime};
 pub use self::load::SimFlags;
 pub use self::modifier::ScenarioModifier;
-pub use self::scenario::{IndividTrip, PersonSpec, Scenario, TripPurpose};
-pub use self::spawner::TripEndpoint;
+pub use self::scenario::{
+    AggregateTargets, IndividTrip, InstantiateOptions, PersonSpec, Scenario, TripLengthStats,
+    TripPurpose,
+};
+pub use self::spawner::{SpawnFailure, SpawnFailureKind, TripEndpoint};
 pub(crate) use self::spawner::{StartTripArgs, TripSpec};
 
 mod activity_model;
@@ -4,21 +4,27 @@ use std::fmt;
 use anyhow::Result;
 use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
 use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
 
 use abstio::MapName;
 use abstutil::{prettyprint_usize, Counter, Timer};
-use geom::{Distance, Speed, Time};
-use map_model::{BuildingID, Map, OffstreetParking, RoadID};
+use geom::{Distance, Duration, PolyLine, Polygon, Speed, Time};
+use map_model::{BuildingID, BusRouteID, Map, OffstreetParking, PathStep, RoadID};
 
 use crate::make::fork_rng;
 use crate::{
-    OrigPersonID, ParkingSpot, Sim, StartTripArgs, TripEndpoint, TripInfo, TripMode, Vehicle,
+    CarID, OrigPersonID, ParkingSpot, PersonID, ScenarioModifier, Sim, SpawnFailure,
+    SpawnFailureKind, StartTripArgs, TripEndpoint, TripInfo, TripMode, TripSpec, Vehicle,
     VehicleSpec, VehicleType, BIKE_LENGTH, MAX_CAR_LENGTH, MIN_CAR_LENGTH,
 };
 
 /// A Scenario describes all the input to a simulation. Usually a scenario covers one day.
+///
+/// Scenarios are saved with bincode, which can't fill in defaults for fields missing from older
+/// files. Adding a field to anything in here makes existing scenario files unreadable, so they
+/// have to be regenerated (see `data/regen.sh`) and the data manifest updated.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Scenario {
     pub scenario_name: String,
@@ -27,19 +33,29 @@ pub struct Scenario {
     pub people: Vec<PersonSpec>,
     /// None means seed all buses. Otherwise the route name must be present here.
     pub only_seed_buses: Option<BTreeSet<String>>,
+    /// The seed used to produce results with this scenario, recorded so they can be reproduced.
+    /// See `instantiate_with_seed`.
+    pub rng_seed: Option<u64>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct PersonSpec {
     /// Just used for debugging
     pub orig_id: Option<OrigPersonID>,
+    /// This person uses a wheelchair, so walking trips should avoid stairs and steep paths. The
+    /// pathfinder doesn't use this yet.
+    pub wheelchair: bool,
+    /// People sharing a home and vehicles can be grouped into the same household. The simulation
+    /// doesn't use this yet, but `Scenario::shard` and `Scenario::merge` keep households
+    /// together. The IDs only mean something within one scenario.
+    pub household: Option<usize>,
     /// There must be continuity between trips: each trip starts at the destination of the previous
     /// trip. In the case of borders, the outbound and inbound border may be different. This means
     /// that there was some sort of "remote" trip happening outside the map that we don't simulate.
     pub trips: Vec<IndividTrip>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct IndividTrip {
     pub depart: Time,
     pub origin: TripEndpoint,
@@ -49,6 +65,21 @@ pub struct IndividTrip {
     pub cancelled: bool,
     /// Did a ScenarioModifier affect this?
     pub modified: bool,
+    /// If the previous trip runs late and this trip can't start within this long of its scheduled
+    /// departure, cancel it instead of waiting. None means wait indefinitely.
+    pub max_start_delay: Option<Duration>,
+    /// Overrides `InstantiateOptions::retry_if_no_room` for this trip, so some vehicles can retry
+    /// spawning while others give up immediately.
+    pub retry_if_no_room: Option<bool>,
+    /// A label for the demand corridor this trip uses, for coloring trips in aggregate. See
+    /// `Scenario::assign_corridors`.
+    pub corridor: Option<String>,
+    /// For driving trips, pin which of the person's cars to use. Cars don't have IDs until the
+    /// scenario is instantiated, so this is a label local to the person; all of their trips with
+    /// the same label use the same car, which must be left where the next trip using it starts;
+    /// `check_schedule` fails otherwise. Without this, a trip reuses whatever car the person has
+    /// parked at the origin, as long as it isn't reserved for a label.
+    pub use_car: Option<usize>,
 }
 
 impl IndividTrip {
@@ -67,12 +98,103 @@ impl IndividTrip {
             purpose,
             cancelled: false,
             modified: false,
+            max_start_delay: None,
+            retry_if_no_room: None,
+            corridor: None,
+            use_car: None,
+        }
+    }
+
+    /// Is the straight-line distance between the trip's endpoints less than `short`?
+    pub fn is_short(&self, map: &Map, short: Distance) -> bool {
+        self.origin.pt(map).dist_to(self.destination.pt(map)) < short
+    }
+}
+
+/// Optional tweaks for how a Scenario is instantiated into a simulation.
+#[derive(Clone, Debug)]
+pub struct InstantiateOptions {
+    /// If false, any vehicles that fail to spawn because of something else in the way will just
+    /// wind up as cancelled trips.
+    pub retry_if_no_room: bool,
+    /// Limit how many trips of some modes can happen at the same time, to model a constrained
+    /// fleet. Trips beyond the cap are cancelled, and show up in `Sim::spawn_failures` as
+    /// `SpawnFailureKind::OverCapacity`. Since trip durations aren't known before
+    /// simulating, each trip is assumed to take as long as traveling in a straight line at a
+    /// typical speed for the mode.
+    pub max_concurrent_trips: BTreeMap<TripMode, usize>,
+    /// Don't seed any parked cars. This is done automatically when no driving trips start from a
+    /// building, but it can be forced for pure transit or walking experiments. Any trips using a
+    /// car that would've been parked are cancelled up-front, since the car won't exist.
+    pub skip_parked_cars: bool,
+    /// Imported data sometimes has trips departing after the simulation will realistically run.
+    /// If set, trips departing after this time are cancelled up-front, instead of silently never
+    /// starting.
+    pub horizon: Option<Time>,
+    /// Before checking each person's schedule, collapse consecutive trips that exactly duplicate
+    /// each other. See `PersonSpec::dedup_simultaneous_trips`.
+    pub dedup_simultaneous_trips: bool,
+}
+
+impl Default for InstantiateOptions {
+    fn default() -> InstantiateOptions {
+        InstantiateOptions {
+            retry_if_no_room: true,
+            max_concurrent_trips: BTreeMap::new(),
+            skip_parked_cars: false,
+            horizon: None,
+            dedup_simultaneous_trips: false,
         }
     }
 }
 
+// Just for export_schedules_json
+#[derive(Serialize)]
+struct PersonSchedule {
+    person: usize,
+    trips: Vec<ScheduledTrip>,
+}
+
+#[derive(Serialize)]
+struct ScheduledTrip {
+    depart_seconds: f64,
+    mode: TripMode,
+    purpose: TripPurpose,
+    from: TripEndpoint,
+    to: TripEndpoint,
+    cancelled: bool,
+}
+
+/// Aggregate statistics describing demand, used to synthesize a `Scenario` without any
+/// microdata. See `Scenario::from_aggregates`.
+#[derive(Clone, Debug)]
+pub struct AggregateTargets {
+    /// How many trips use each mode
+    pub trips_per_mode: BTreeMap<TripMode, usize>,
+    /// The relative frequency of each trip purpose. These don't need to sum to 1. If this is
+    /// empty, every trip is for shopping.
+    pub purpose_weights: BTreeMap<TripPurpose, f64>,
+    /// Departure times cluster around these peaks, chosen evenly. If this is empty, departures
+    /// are spread uniformly over the day.
+    pub peaks: Vec<Time>,
+    /// The standard deviation of departure times around each peak
+    pub peak_spread: Duration,
+}
+
+/// A summary of how far trips in a `Scenario` go, estimated without simulating. See
+/// `Scenario::trip_length_stats`.
+#[derive(Clone, Debug)]
+pub struct TripLengthStats {
+    pub num_trips: usize,
+    /// Straight-line distance between the trip's endpoints
+    pub mean_distance: Distance,
+    pub median_distance: Distance,
+    /// Traveling the straight-line distance at a typical speed for the mode
+    pub mean_duration_per_mode: BTreeMap<TripMode, Duration>,
+}
+
 /// Lifted from Seattle's Soundcast model, but seems general enough to use anyhere.
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TripPurpose {
     Home,
     Work,
@@ -115,6 +237,16 @@ impl Scenario {
         self.instantiate_without_retries(sim, map, rng, true, timer);
     }
 
+    /// Like `instantiate`, but creates the RNG from this scenario's `rng_seed`, so the results are
+    /// reproducible from the scenario alone. Without a seed, uses `deterministic_rng`.
+    pub fn instantiate_with_seed(&self, sim: &mut Sim, map: &Map, timer: &mut Timer) {
+        let mut rng = match self.rng_seed {
+            Some(seed) => XorShiftRng::seed_from_u64(seed),
+            None => Scenario::deterministic_rng(),
+        };
+        self.instantiate(sim, map, &mut rng, timer);
+    }
+
     /// If retry_if_no_room is false, any vehicles that fail to spawn because of something else in
     /// the way will just wind up as cancelled trips.
     pub fn instantiate_without_retries(
@@ -124,6 +256,26 @@ impl Scenario {
         rng: &mut XorShiftRng,
         retry_if_no_room: bool,
         timer: &mut Timer,
+    ) {
+        self.instantiate_with_options(
+            sim,
+            map,
+            rng,
+            InstantiateOptions {
+                retry_if_no_room,
+                ..Default::default()
+            },
+            timer,
+        );
+    }
+
+    pub fn instantiate_with_options(
+        &self,
+        sim: &mut Sim,
+        map: &Map,
+        rng: &mut XorShiftRng,
+        opts: InstantiateOptions,
+        timer: &mut Timer,
     ) {
         // Any case where map edits could change the calls to the RNG, we have to fork.
         sim.set_run_name(self.scenario_name.clone());
@@ -146,16 +298,35 @@ impl Scenario {
         timer.start_iter("trips for People", self.people.len());
         let mut parked_cars: Vec<(Vehicle, BuildingID)> = Vec::new();
         let mut schedule_trips = Vec::new();
+        let mut num_deduped = 0;
         for p in &self.people {
             timer.next();
 
+            // Only copy the people that have something to dedupe
+            let deduped = if opts.dedup_simultaneous_trips
+                && p.trips.windows(2).any(|pair| pair[0] == pair[1])
+            {
+                let mut p = p.clone();
+                num_deduped += p.dedup_simultaneous_trips();
+                Some(p)
+            } else {
+                None
+            };
+            let p = deduped.as_ref().unwrap_or(p);
+
             if let Err(err) = p.check_schedule() {
                 panic!("{}", err);
             }
 
             let (vehicle_specs, cars_initially_parked_at, vehicle_foreach_trip) =
                 p.get_vehicles(rng);
-            let person = sim.new_person(p.orig_id, Scenario::rand_ped_speed(rng), vehicle_specs);
+            let person = sim.new_person(
+                p.orig_id,
+                Scenario::rand_ped_speed(rng),
+                p.wheelchair,
+                p.household,
+                vehicle_specs,
+            );
             for (idx, b) in cars_initially_parked_at {
                 parked_cars.push((person.vehicles[idx].clone(), b));
             }
@@ -176,16 +347,72 @@ impl Scenario {
                         },
                     },
                     StartTripArgs {
-                        retry_if_no_room,
+                        retry_if_no_room: trip.retry_if_no_room.unwrap_or(opts.retry_if_no_room),
                         use_vehicle: maybe_idx.map(|idx| person.vehicles[idx].id),
+                        max_start_delay: trip.max_start_delay,
                     },
+                    None,
                 ));
             }
         }
 
-        // parked_cars is stable over map edits, so don't fork.
-        parked_cars.shuffle(rng);
-        seed_parked_cars(parked_cars, sim, map, rng, timer);
+        if num_deduped > 0 {
+            warn!(
+                "Merged {} trips that exactly duplicated the previous trip",
+                prettyprint_usize(num_deduped)
+            );
+        }
+
+        if opts.skip_parked_cars || parked_cars.is_empty() {
+            // Even with no cars, setting up all of the parking spots is expensive
+            info!("Not seeding any parked cars");
+            let unseeded: BTreeSet<CarID> = parked_cars.iter().map(|(v, _)| v.id).collect();
+            let num_cancelled = cancel_trips_using_cars(&mut schedule_trips, &unseeded);
+            if num_cancelled > 0 {
+                warn!(
+                    "Cancelled {} trips that needed a parked car",
+                    prettyprint_usize(num_cancelled)
+                );
+            }
+        } else {
+            // parked_cars is stable over map edits, so don't fork.
+            parked_cars.shuffle(rng);
+            seed_parked_cars(parked_cars, sim, map, rng, timer);
+        }
+
+        if !opts.max_concurrent_trips.is_empty() {
+            let num_capped =
+                cap_concurrent_trips(&mut schedule_trips, &opts.max_concurrent_trips, map);
+            if num_capped > 0 {
+                warn!(
+                    "Cancelled {} trips to respect the caps on concurrent trips",
+                    prettyprint_usize(num_capped)
+                );
+            }
+        }
+
+        if let Some(horizon) = opts.horizon {
+            let mut num_late = 0;
+            for (_, info, _, failure) in &mut schedule_trips {
+                if info.departure > horizon
+                    && info.cancellation_reason.is_none()
+                    && failure.is_none()
+                {
+                    info.cancellation_reason = Some(format!(
+                        "departs after the simulation horizon of {}",
+                        horizon
+                    ));
+                    num_late += 1;
+                }
+            }
+            if num_late > 0 {
+                warn!(
+                    "Cancelled {} trips departing after {}",
+                    prettyprint_usize(num_late),
+                    horizon
+                );
+            }
+        }
 
         sim.spawn_trips(schedule_trips, map, timer);
         timer.stop(format!("Instantiating {}", self.scenario_name));
@@ -204,7 +431,171 @@ impl Scenario {
             map_name: map.get_name().clone(),
             people: Vec::new(),
             only_seed_buses: Some(BTreeSet::new()),
+            rng_seed: None,
+        }
+    }
+
+    /// Generates a reproducible scenario for tests. Each person walks from a random building to a
+    /// different one and later returns home. Departures are spread evenly over the day, and every
+    /// person passes `check_schedule`. The map must have at least two buildings.
+    pub fn synthetic_grid(map: &Map, people: usize, rng: &mut XorShiftRng) -> Scenario {
+        let bldgs = map.all_buildings();
+        assert!(
+            bldgs.len() >= 2,
+            "synthetic_grid needs at least two buildings"
+        );
+
+        let mut scenario = Scenario::empty(map, "synthetic grid");
+        // Leave the first 6 hours of the day empty, then space out departures over 12 hours
+        let spacing = Duration::hours(12) / (people.max(1) as f64);
+        for idx in 0..people {
+            let depart = Time::START_OF_DAY + Duration::hours(6) + spacing * (idx as f64);
+            let mut pair = bldgs.choose_multiple(rng, 2);
+            let home = TripEndpoint::Bldg(pair.next().unwrap().id);
+            let work = TripEndpoint::Bldg(pair.next().unwrap().id);
+            scenario.people.push(PersonSpec {
+                orig_id: None,
+                wheelchair: false,
+                household: None,
+                trips: vec![
+                    IndividTrip::new(depart, TripPurpose::Work, home, work, TripMode::Walk),
+                    IndividTrip::new(
+                        depart + Duration::hours(4),
+                        TripPurpose::Home,
+                        work,
+                        home,
+                        TripMode::Walk,
+                    ),
+                ],
+            });
+        }
+        scenario
+    }
+
+    /// Synthesizes a typical day matching some aggregate statistics, useful when no microdata is
+    /// available. Every trip belongs to a different person and goes between two random
+    /// buildings. Endpoints are resampled a few times until the mode can actually make the trip;
+    /// if that still fails, the trip is skipped, so the totals may fall slightly short.
+    pub fn from_aggregates(
+        map: &Map,
+        targets: &AggregateTargets,
+        rng: &mut XorShiftRng,
+    ) -> Scenario {
+        let bldgs = map.all_buildings();
+        assert!(
+            bldgs.len() >= 2,
+            "from_aggregates needs at least two buildings"
+        );
+        let purposes: Vec<(TripPurpose, f64)> = targets
+            .purpose_weights
+            .iter()
+            .map(|(purpose, weight)| (*purpose, *weight))
+            .collect();
+        let end_of_day = Duration::hours(24).inner_seconds();
+
+        let mut scenario = Scenario::empty(map, "synthetic from aggregates");
+        let mut skipped = 0;
+        for (mode, count) in &targets.trips_per_mode {
+            for _ in 0..*count {
+                let mut endpoints = None;
+                for _ in 0..10 {
+                    let mut pair = bldgs.choose_multiple(rng, 2);
+                    let from = TripEndpoint::Bldg(pair.next().unwrap().id);
+                    let to = TripEndpoint::Bldg(pair.next().unwrap().id);
+                    if TripEndpoint::path_req(from, to, *mode, map)
+                        .and_then(|req| map.pathfind(req).ok())
+                        .is_some()
+                    {
+                        endpoints = Some((from, to));
+                        break;
+                    }
+                }
+                let (from, to) = match endpoints {
+                    Some(pair) => pair,
+                    None => {
+                        skipped += 1;
+                        continue;
+                    }
+                };
+
+                let purpose = purposes
+                    .choose_weighted(rng, |(_, weight)| *weight)
+                    .map(|(purpose, _)| *purpose)
+                    .unwrap_or(TripPurpose::Shopping);
+                let secs = match targets.peaks.choose(rng) {
+                    Some(peak) => {
+                        Normal::new(peak.inner_seconds(), targets.peak_spread.inner_seconds())
+                            .unwrap()
+                            .sample(rng)
+                    }
+                    None => rng.gen_range(0.0..end_of_day),
+                };
+                let depart = Time::START_OF_DAY + Duration::seconds(secs.max(0.0).min(end_of_day));
+
+                scenario.people.push(PersonSpec {
+                    orig_id: None,
+                    wheelchair: false,
+                    household: None,
+                    trips: vec![IndividTrip::new(depart, purpose, from, to, *mode)],
+                });
+            }
+        }
+        if skipped > 0 {
+            warn!(
+                "Couldn't find endpoints for {} synthetic trips",
+                prettyprint_usize(skipped)
+            );
+        }
+        scenario
+    }
+
+    /// Builds people who only ride transit from a table, such as one derived from GTFS. Each row
+    /// is (person, departure, origin, destination, purpose), and all rows for the same person
+    /// become one `PersonSpec`, with trips sorted by departure. People whose trips don't form a
+    /// valid schedule, like two trips departing at once, are skipped. Returns the people, the
+    /// indices of rows where no useful transit route connects the endpoints (those trips will
+    /// just walk), and the indices of rows belonging to skipped people.
+    pub fn transit_riders_from_table(
+        rows: &[(usize, Time, TripEndpoint, TripEndpoint, TripPurpose)],
+        map: &Map,
+    ) -> (Vec<PersonSpec>, Vec<usize>, Vec<usize>) {
+        let mut trips_per_person: BTreeMap<usize, Vec<(usize, IndividTrip)>> = BTreeMap::new();
+        let mut unroutable = Vec::new();
+        for (idx, (person, depart, from, to, purpose)) in rows.iter().enumerate() {
+            if !matches!(
+                TripSpec::maybe_new(*from, *to, TripMode::Transit, None, false, false, map),
+                Ok(TripSpec::UsingTransit { .. })
+            ) {
+                unroutable.push(idx);
+            }
+            trips_per_person
+                .entry(*person)
+                .or_insert_with(Vec::new)
+                .push((
+                    idx,
+                    IndividTrip::new(*depart, *purpose, *from, *to, TripMode::Transit),
+                ));
+        }
+
+        let mut people = Vec::new();
+        let mut skipped = Vec::new();
+        for (id, mut trips) in trips_per_person {
+            trips.sort_by(|a, b| a.1.depart.partial_cmp(&b.1.depart).unwrap());
+            let person = PersonSpec {
+                orig_id: None,
+                wheelchair: false,
+                household: None,
+                trips: trips.iter().map(|(_, trip)| trip.clone()).collect(),
+            };
+            if let Err(err) = person.check_schedule() {
+                warn!("Skipping transit rider {} from the table: {}", id, err);
+                skipped.extend(trips.into_iter().map(|(idx, _)| idx));
+            } else {
+                people.push(person);
+            }
         }
+        skipped.sort_unstable();
+        (people, unroutable, skipped)
     }
 
     fn rand_car(rng: &mut XorShiftRng) -> VehicleSpec {
@@ -249,10 +640,17 @@ impl Scenario {
         )
     }
 
+    /// Some structural queries over a scenario need an RNG for calls that don't affect their
+    /// result. Use this as the single source of a fixed-seed RNG, so those analyses can't
+    /// accidentally diverge.
+    pub fn deterministic_rng() -> XorShiftRng {
+        XorShiftRng::seed_from_u64(0)
+    }
+
     pub fn count_parked_cars_per_bldg(&self) -> Counter<BuildingID> {
         let mut per_bldg = Counter::new();
-        // Pass in a dummy RNG
-        let mut rng = XorShiftRng::seed_from_u64(0);
+        // The RNG only affects vehicle specs, not where they're parked
+        let mut rng = Scenario::deterministic_rng();
         for p in &self.people {
             let (_, cars_initially_parked_at, _) = p.get_vehicles(&mut rng);
             for (_, b) in cars_initially_parked_at {
@@ -262,6 +660,22 @@ impl Scenario {
         per_bldg
     }
 
+    /// Count how many trips start and end at each building, returning (origins, destinations).
+    /// Trips from or to borders, or suddenly appearing somewhere, aren't counted.
+    pub fn trips_per_bldg(&self) -> (Counter<BuildingID>, Counter<BuildingID>) {
+        let mut origins = Counter::new();
+        let mut destinations = Counter::new();
+        for trip in self.all_trips() {
+            if let TripEndpoint::Bldg(b) = trip.origin {
+                origins.inc(b);
+            }
+            if let TripEndpoint::Bldg(b) = trip.destination {
+                destinations.inc(b);
+            }
+        }
+        (origins, destinations)
+    }
+
     pub fn remove_weird_schedules(mut self) -> Scenario {
         let orig = self.people.len();
         self.people.retain(|person| match person.check_schedule() {
@@ -279,11 +693,1175 @@ impl Scenario {
         self
     }
 
+    /// Imported data sometimes lists the exact same trip twice in a row for a person, which
+    /// `check_schedule` would otherwise reject. Collapse these duplicates, returning the number of
+    /// trips removed. Call this before `remove_weird_schedules` to keep these people, or use
+    /// `InstantiateOptions::dedup_simultaneous_trips` to leave the scenario itself untouched.
+    pub fn dedup_simultaneous_trips(&mut self) -> usize {
+        let mut merged = 0;
+        for person in &mut self.people {
+            merged += person.dedup_simultaneous_trips();
+        }
+        if merged > 0 {
+            warn!(
+                "Merged {} trips that exactly duplicated the previous trip",
+                prettyprint_usize(merged)
+            );
+        }
+        merged
+    }
+
+    /// Imported demand sometimes lists the same person more than once. Remove people whose entire
+    /// schedule exactly matches somebody earlier, returning the number removed. Only the original
+    /// ID may differ.
+    pub fn dedup_people(&mut self) -> usize {
+        let orig = self.people.len();
+        // Group by the places and modes first, so only a few people have to be compared exactly
+        let mut seen: BTreeMap<Vec<(TripEndpoint, TripEndpoint, TripMode)>, Vec<usize>> =
+            BTreeMap::new();
+        let mut keep = Vec::with_capacity(orig);
+        for (idx, person) in self.people.iter().enumerate() {
+            let key = person
+                .trips
+                .iter()
+                .map(|t| (t.origin, t.destination, t.mode))
+                .collect();
+            let candidates = seen.entry(key).or_insert_with(Vec::new);
+            let duplicate = candidates.iter().any(|other| {
+                let other = &self.people[*other];
+                other.wheelchair == person.wheelchair && other.trips == person.trips
+            });
+            if !duplicate {
+                candidates.push(idx);
+            }
+            keep.push(!duplicate);
+        }
+        let mut keep = keep.into_iter();
+        self.people.retain(|_| keep.next().unwrap());
+        let removed = orig - self.people.len();
+        if removed > 0 {
+            warn!(
+                "Removed {} people who duplicated somebody else",
+                prettyprint_usize(removed)
+            );
+        }
+        removed
+    }
+
+    /// Make people whose first trip doesn't start at a building begin the day at home instead.
+    /// Someone's home is the destination of any of their trips with `TripPurpose::Home`. People
+    /// without a home building are left alone, as are cases where the first trip couldn't happen
+    /// from home, because it would go nowhere or pathfinding fails. Returns the number of people
+    /// changed.
+    pub fn anchor_to_home(&mut self, map: &Map) -> usize {
+        let mut changed = 0;
+        let mut no_home = 0;
+        for person in &mut self.people {
+            match person.trips.first() {
+                Some(trip) if !matches!(trip.origin, TripEndpoint::Bldg(_)) => {}
+                _ => continue,
+            }
+            let home = person.trips.iter().find_map(|t| match t.destination {
+                TripEndpoint::Bldg(b) if t.purpose == TripPurpose::Home => Some(b),
+                _ => None,
+            });
+            let home = if let Some(b) = home {
+                TripEndpoint::Bldg(b)
+            } else {
+                no_home += 1;
+                continue;
+            };
+
+            let first = &mut person.trips[0];
+            if first.destination == home {
+                continue;
+            }
+            // Only the origin changes, so the rest of the schedule stays connected
+            if TripEndpoint::path_req(home, first.destination, first.mode, map)
+                .and_then(|req| map.pathfind(req).ok())
+                .is_some()
+            {
+                first.origin = home;
+                first.modified = true;
+                changed += 1;
+            }
+        }
+        if changed > 0 || no_home > 0 {
+            warn!(
+                "Anchored {} people to start at home. {} others don't start at a building, but \
+                 have no known home",
+                prettyprint_usize(changed),
+                prettyprint_usize(no_home)
+            );
+        }
+        changed
+    }
+
+    /// Finds trips that start and end at the same place, which `check_schedule` rejects. Returns
+    /// the person and the index of the trip.
+    pub fn find_zero_length_trips(&self) -> Vec<(PersonID, usize)> {
+        let mut results = Vec::new();
+        for (idx, person) in self.people.iter().enumerate() {
+            for (trip_idx, trip) in person.trips.iter().enumerate() {
+                if trip.origin == trip.destination {
+                    results.push((PersonID(idx), trip_idx));
+                }
+            }
+        }
+        results
+    }
+
+    /// Removes trips that start and end at the same place, returning the number removed. Since
+    /// such a trip doesn't move the person, the trips around it still connect. People may be left
+    /// with no trips at all; see `drop_empty_people`.
+    pub fn drop_zero_length_trips(&mut self) -> usize {
+        let mut removed = 0;
+        for person in &mut self.people {
+            let orig = person.trips.len();
+            person.trips.retain(|t| t.origin != t.destination);
+            removed += orig - person.trips.len();
+        }
+        if removed > 0 {
+            warn!(
+                "Removed {} trips that start and end at the same place",
+                prettyprint_usize(removed)
+            );
+        }
+        removed
+    }
+
+    /// Remove people without any trips, which can happen after filtering trips. Since a person's
+    /// ID is their position in `people`, later people get new IDs. Returns the number removed.
+    pub fn drop_empty_people(&mut self) -> usize {
+        let orig = self.people.len();
+        self.people.retain(|p| !p.trips.is_empty());
+        orig - self.people.len()
+    }
+
+    /// People generated programmatically have no `orig_id`, which makes them hard to trace while
+    /// debugging. Assign `OrigPersonID(tag, idx)` to everybody without one, where `idx` is their
+    /// current position in `people`. Using a different `tag` for each generator distinguishes
+    /// people from different sources. Returns the number of people assigned.
+    pub fn assign_synthetic_orig_ids(&mut self, tag: usize) -> usize {
+        // OrigPersonID is serialized as a pair of u32s
+        assert!(
+            tag <= u32::MAX as usize && self.people.len() <= u32::MAX as usize,
+            "tag {} or {} people won't fit in an OrigPersonID",
+            tag,
+            self.people.len()
+        );
+        let mut assigned = 0;
+        for (idx, person) in self.people.iter_mut().enumerate() {
+            if person.orig_id.is_none() {
+                person.orig_id = Some(OrigPersonID(tag, idx));
+                assigned += 1;
+            }
+        }
+        assigned
+    }
+
+    /// Apply a sequence of modifiers in order, in place. Every modifier marks the trips it touches
+    /// as `modified`, and none of them are random, so the result is deterministic. Returns the
+    /// number of trips marked as modified before and after. Some modifiers remove trips, so there
+    /// may be fewer afterwards.
+    pub fn apply_modifiers(&mut self, map: &Map, modifiers: &[ScenarioModifier]) -> (usize, usize) {
+        let count_modified = |s: &Scenario| s.all_trips().filter(|t| t.modified).count();
+        let before = count_modified(self);
+        let mut scenario = std::mem::replace(self, Scenario::empty(map, ""));
+        for m in modifiers {
+            info!("Modifying {}: {}", scenario.scenario_name, m.describe());
+            scenario = m.apply(map, scenario);
+        }
+        *self = scenario;
+        let after = count_modified(self);
+        if !modifiers.is_empty() {
+            info!(
+                "After {} modifiers, {} trips are modified, compared to {} before",
+                modifiers.len(),
+                prettyprint_usize(after),
+                prettyprint_usize(before)
+            );
+        }
+        (before, after)
+    }
+
+    /// Randomly reorders people, which is deterministic given the RNG. Since a person's ID is just
+    /// their position in `people`, this also reassigns IDs.
+    pub fn shuffle_people(&mut self, rng: &mut XorShiftRng) {
+        self.people.shuffle(rng);
+    }
+
+    /// Some data splits one journey into legs at waypoints. Merge consecutive trips of a person
+    /// when the first ends where the second starts, both use the same mode, and the second
+    /// departs within 5 minutes of when the first would arrive. Arrival is estimated by traveling
+    /// in a straight line at a typical speed for the mode. This avoids spurious parking and
+    /// unparking between artificial legs. Returns the number of merges.
+    pub fn collapse_waypoint_legs(&mut self, map: &Map) -> usize {
+        let max_gap = Duration::minutes(5);
+        let mut merges = 0;
+        for person in &mut self.people {
+            let mut trips: Vec<IndividTrip> = Vec::new();
+            for trip in person.trips.drain(..) {
+                if let Some(prev) = trips.last_mut() {
+                    let arrival = prev.depart
+                        + estimate_duration(prev.mode, prev.origin, prev.destination, map);
+                    if prev.destination == trip.origin
+                        && prev.mode == trip.mode
+                        // Don't create a round-trip to the same place
+                        && prev.origin != trip.destination
+                        && trip.depart - arrival <= max_gap
+                    {
+                        prev.destination = trip.destination;
+                        prev.purpose = trip.purpose;
+                        prev.modified = true;
+                        merges += 1;
+                        continue;
+                    }
+                }
+                trips.push(trip);
+            }
+            person.trips = trips;
+        }
+        merges
+    }
+
+    /// Returns a random subset of `n` people (or everybody, if there aren't that many), useful for
+    /// quickly previewing demand. People keep their relative order, and bus seeding is preserved.
+    pub fn sample(&self, n: usize, rng: &mut XorShiftRng) -> Scenario {
+        let mut indices =
+            rand::seq::index::sample(rng, self.people.len(), n.min(self.people.len())).into_vec();
+        indices.sort_unstable();
+        Scenario {
+            scenario_name: format!("{} (sample of {})", self.scenario_name, n),
+            map_name: self.map_name.clone(),
+            people: indices
+                .into_iter()
+                .map(|idx| self.people[idx].clone())
+                .collect(),
+            only_seed_buses: self.only_seed_buses.clone(),
+            rng_seed: self.rng_seed,
+        }
+    }
+
+    /// Split people into `n` scenarios of roughly equal size, such as to simulate pieces of a
+    /// huge scenario separately. Members of a household always wind up in the same shard. People
+    /// keep their relative order within each shard, so `PersonID`s change.
+    pub fn shard(&self, n: usize) -> Vec<Scenario> {
+        assert!(n > 0, "can't split into 0 shards");
+        let mut shards: Vec<Scenario> = (0..n)
+            .map(|idx| Scenario {
+                scenario_name: format!("{} (shard {} of {})", self.scenario_name, idx + 1, n),
+                map_name: self.map_name.clone(),
+                people: Vec::new(),
+                only_seed_buses: self.only_seed_buses.clone(),
+                rng_seed: self.rng_seed,
+            })
+            .collect();
+        // Deal out households and people without one in turn
+        let mut household_to_shard: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut next_shard = 0;
+        for person in &self.people {
+            let idx = match person.household {
+                Some(h) => *household_to_shard.entry(h).or_insert_with(|| {
+                    next_shard += 1;
+                    (next_shard - 1) % n
+                }),
+                None => {
+                    next_shard += 1;
+                    (next_shard - 1) % n
+                }
+            };
+            shards[idx].people.push(person.clone());
+        }
+        shards
+    }
+
+    /// Combine scenarios for the same map into one, such as the output of `shard`. Household IDs
+    /// are renumbered, so households from different scenarios stay separate, while members of
+    /// one household stay together. Buses are seeded if any input seeds them.
+    pub fn merge(scenarios: Vec<Scenario>) -> Result<Scenario> {
+        let mut iter = scenarios.into_iter();
+        let mut merged = match iter.next() {
+            Some(s) => s,
+            None => bail!("nothing to merge"),
+        };
+        let mut next_household = merged
+            .people
+            .iter()
+            .filter_map(|p| p.household)
+            .max()
+            .map(|h| h + 1)
+            .unwrap_or(0);
+        for scenario in iter {
+            if scenario.map_name != merged.map_name {
+                bail!(
+                    "can't merge scenarios for {:?} and {:?}",
+                    merged.map_name,
+                    scenario.map_name
+                );
+            }
+            merged.only_seed_buses = match (merged.only_seed_buses.take(), scenario.only_seed_buses)
+            {
+                (Some(mut routes), Some(more)) => {
+                    routes.extend(more);
+                    Some(routes)
+                }
+                // Seeding all buses wins
+                _ => None,
+            };
+            if merged.rng_seed != scenario.rng_seed {
+                merged.rng_seed = None;
+            }
+
+            let mut renumber: BTreeMap<usize, usize> = BTreeMap::new();
+            for mut person in scenario.people {
+                person.household = person.household.map(|h| {
+                    *renumber.entry(h).or_insert_with(|| {
+                        next_household += 1;
+                        next_household - 1
+                    })
+                });
+                merged.people.push(person);
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Blends demand between two scenarios on the same map, such as for animating growth. People
+    /// identical in both are always kept. Of the people only in `a`, a random `1 - t` fraction is
+    /// kept, and of the people only in `b`, a random `t` fraction is added. So `t = 0` gives `a`
+    /// and `t = 1` gives `b`, up to the order of people.
+    pub fn interpolate(
+        a: &Scenario,
+        b: &Scenario,
+        t: f64,
+        rng: &mut XorShiftRng,
+    ) -> Result<Scenario> {
+        if a.map_name != b.map_name {
+            bail!(
+                "Can't interpolate between scenarios on {} and {}",
+                a.map_name.describe(),
+                b.map_name.describe()
+            );
+        }
+        if !(0.0..=1.0).contains(&t) {
+            bail!("Interpolating needs 0 <= t <= 1, not {}", t);
+        }
+
+        // PersonSpec can't be compared directly because of floating point, so match people by
+        // their serialized form. There may be duplicate people, so track every copy.
+        let mut unmatched_b: BTreeMap<Vec<u8>, Vec<usize>> = BTreeMap::new();
+        for (idx, person) in b.people.iter().enumerate() {
+            unmatched_b
+                .entry(abstutil::to_binary(person))
+                .or_insert_with(Vec::new)
+                .push(idx);
+        }
+        let mut common = Vec::new();
+        let mut only_a = Vec::new();
+        for (idx, person) in a.people.iter().enumerate() {
+            match unmatched_b
+                .get_mut(&abstutil::to_binary(person))
+                .and_then(|indices| indices.pop())
+            {
+                Some(_) => common.push(idx),
+                None => only_a.push(idx),
+            }
+        }
+        let mut only_b: Vec<usize> = unmatched_b.into_values().flatten().collect();
+        only_b.sort_unstable();
+
+        let mut keep_a = common;
+        let n = ((1.0 - t) * only_a.len() as f64).round() as usize;
+        keep_a.extend(
+            rand::seq::index::sample(rng, only_a.len(), n)
+                .into_iter()
+                .map(|i| only_a[i]),
+        );
+        keep_a.sort_unstable();
+        let n = (t * only_b.len() as f64).round() as usize;
+        let mut keep_b: Vec<usize> = rand::seq::index::sample(rng, only_b.len(), n)
+            .into_iter()
+            .map(|i| only_b[i])
+            .collect();
+        keep_b.sort_unstable();
+
+        Ok(Scenario {
+            scenario_name: format!("{} to {} ({:.2})", a.scenario_name, b.scenario_name, t),
+            map_name: a.map_name.clone(),
+            people: keep_a
+                .into_iter()
+                .map(|idx| a.people[idx].clone())
+                .chain(keep_b.into_iter().map(|idx| b.people[idx].clone()))
+                .collect(),
+            only_seed_buses: if t < 0.5 {
+                a.only_seed_buses.clone()
+            } else {
+                b.only_seed_buses.clone()
+            },
+            rng_seed: None,
+        })
+    }
+
+    /// Many imported datasets only include outbound trips. For each person whose last trip doesn't
+    /// end where their first trip started, add a trip back there sometime 4-12 hours later, using
+    /// the same mode as the last trip. Returns the number of people modified.
+    pub fn add_return_trips(&mut self, rng: &mut XorShiftRng) -> usize {
+        let mut cnt = 0;
+        for person in &mut self.people {
+            let (home, last) = match (person.trips.first(), person.trips.last()) {
+                (Some(first), Some(last)) => (first.origin, last),
+                _ => continue,
+            };
+            if last.destination == home {
+                continue;
+            }
+            // SuddenlyAppear is only valid as the start of a trip
+            if matches!(home, TripEndpoint::SuddenlyAppear(_))
+                || matches!(last.destination, TripEndpoint::SuddenlyAppear(_))
+            {
+                continue;
+            }
+
+            // Assume a uniform distribution of 4-12 hour activity
+            let depart = last.depart
+                + Duration::seconds(rng.gen_range(
+                    Duration::hours(4).inner_seconds()..Duration::hours(12).inner_seconds(),
+                ));
+            let mut trip =
+                IndividTrip::new(depart, TripPurpose::Home, last.destination, home, last.mode);
+            trip.modified = true;
+            person.trips.push(trip);
+            cnt += 1;
+        }
+        cnt
+    }
+
+    /// Counts trips departing after some time, like the end of the day. Useful for validating
+    /// imported data before running it.
+    pub fn count_trips_departing_after(&self, horizon: Time) -> usize {
+        self.all_trips().filter(|t| t.depart > horizon).count()
+    }
+
+    /// Synthetic demand often has unrealistically flat departure times. Resample every departure
+    /// from a mix of two normal distributions centered on the morning and evening peaks. Each
+    /// person's trips keep their original order, and depart at least a second apart, so
+    /// `check_schedule` still passes. Returns the number of trips modified.
+    pub fn apply_peak_profile(
+        &mut self,
+        morning_peak: Time,
+        evening_peak: Time,
+        spread: Duration,
+        rng: &mut XorShiftRng,
+    ) -> usize {
+        let morning = Normal::new(morning_peak.inner_seconds(), spread.inner_seconds()).unwrap();
+        let evening = Normal::new(evening_peak.inner_seconds(), spread.inner_seconds()).unwrap();
+        let end_of_day = Duration::hours(24).inner_seconds();
+
+        let mut cnt = 0;
+        for person in &mut self.people {
+            let mut times: Vec<f64> = person
+                .trips
+                .iter()
+                .map(|_| {
+                    let dist = if rng.gen_bool(0.5) {
+                        &morning
+                    } else {
+                        &evening
+                    };
+                    dist.sample(rng).max(0.0).min(end_of_day)
+                })
+                .collect();
+            times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            // Clamping makes ties likely near midnight, but a person can't start two trips at
+            // once
+            let mut prev = f64::NEG_INFINITY;
+            for secs in &mut times {
+                *secs = secs.max(prev + 1.0);
+                prev = *secs;
+            }
+            for (trip, secs) in person.trips.iter_mut().zip(times) {
+                trip.depart = Time::START_OF_DAY + Duration::seconds(secs);
+                trip.modified = true;
+                cnt += 1;
+            }
+        }
+        cnt
+    }
+
+    /// Changes the mode of every trip matching the predicate, as long as the new mode can actually
+    /// get between the trip's endpoints. Trips already using the new mode are left alone. Returns
+    /// the number of trips changed and the number skipped because the new mode wasn't feasible.
+    pub fn retarget_mode<F: Fn(&IndividTrip) -> bool>(
+        &mut self,
+        pred: F,
+        new_mode: TripMode,
+        map: &Map,
+    ) -> (usize, usize) {
+        let mut changed = 0;
+        let mut infeasible = 0;
+        for person in &mut self.people {
+            for trip in &mut person.trips {
+                if trip.mode == new_mode || !pred(trip) {
+                    continue;
+                }
+                let feasible = trip.origin.check_border(new_mode, true, map).is_ok()
+                    && trip.destination.check_border(new_mode, false, map).is_ok()
+                    && TripEndpoint::path_req(trip.origin, trip.destination, new_mode, map)
+                        .and_then(|req| map.pathfind(req).ok())
+                        .is_some();
+                if feasible {
+                    trip.mode = new_mode;
+                    trip.modified = true;
+                    changed += 1;
+                } else {
+                    infeasible += 1;
+                }
+            }
+        }
+        (changed, infeasible)
+    }
+
+    /// For sensitivity analysis, shift every departure by a uniformly random amount within
+    /// `[-max, max]`, never before midnight. Each person's trips are re-sorted. If the noise
+    /// reorders a person's trips so their schedule no longer makes sense, that person keeps their
+    /// original departures. Returns the number of people left unchanged this way.
+    pub fn jitter_departures(&mut self, max: Duration, rng: &mut XorShiftRng) -> usize {
+        let max = max.inner_seconds();
+        let mut unchanged = 0;
+        for person in &mut self.people {
+            let orig = person.trips.clone();
+            for trip in &mut person.trips {
+                let noise = if max > 0.0 {
+                    rng.gen_range(-max..=max)
+                } else {
+                    0.0
+                };
+                let secs = (trip.depart.inner_seconds() + noise).max(0.0);
+                trip.depart = Time::START_OF_DAY + Duration::seconds(secs);
+                trip.modified = true;
+            }
+            person
+                .trips
+                .sort_by(|a, b| a.depart.partial_cmp(&b.depart).unwrap());
+            if person.check_schedule().is_err() {
+                person.trips = orig;
+                unchanged += 1;
+            }
+        }
+        unchanged
+    }
+
+    /// A quick estimate of vehicle-miles-traveled, without simulating anything. This sums the
+    /// straight-line distance between the endpoints of all driving trips, so it underestimates the
+    /// real distance driven along roads. Cancelled trips are skipped.
+    pub fn estimated_vmt(&self, map: &Map) -> Distance {
+        self.all_trips()
+            .filter(|t| t.mode == TripMode::Drive && !t.cancelled)
+            .map(|t| t.origin.pt(map).dist_to(t.destination.pt(map)))
+            .sum()
+    }
+
+    /// Summarizes how far and how long trips are, for a quick overview of demand. Distances are
+    /// straight lines between endpoints, and durations assume a typical speed for each mode, so
+    /// both underestimate reality. Cancelled trips are skipped.
+    pub fn trip_length_stats(&self, map: &Map) -> TripLengthStats {
+        let mut distances = Vec::new();
+        let mut durations: BTreeMap<TripMode, Vec<Duration>> = BTreeMap::new();
+        for trip in self.all_trips().filter(|t| !t.cancelled) {
+            distances.push(trip.origin.pt(map).dist_to(trip.destination.pt(map)));
+            durations
+                .entry(trip.mode)
+                .or_insert_with(Vec::new)
+                .push(estimate_duration(
+                    trip.mode,
+                    trip.origin,
+                    trip.destination,
+                    map,
+                ));
+        }
+        distances.sort();
+
+        let num_trips = distances.len();
+        TripLengthStats {
+            num_trips,
+            mean_distance: if num_trips == 0 {
+                Distance::ZERO
+            } else {
+                distances.iter().cloned().sum::<Distance>() / (num_trips as f64)
+            },
+            median_distance: distances
+                .get(num_trips / 2)
+                .cloned()
+                .unwrap_or(Distance::ZERO),
+            mean_duration_per_mode: durations
+                .into_iter()
+                .map(|(mode, list)| {
+                    let n = list.len() as f64;
+                    (mode, list.into_iter().sum::<Duration>() / n)
+                })
+                .collect(),
+        }
+    }
+
+    /// Counts trips shorter than some straight-line distance, useful for judging how much demand
+    /// could be served by walking. Returns (short, long). See `IndividTrip::is_short`.
+    pub fn classify_trip_lengths(&self, map: &Map, short: Distance) -> (usize, usize) {
+        let num_short = self.all_trips().filter(|t| t.is_short(map, short)).count();
+        (num_short, self.all_trips().count() - num_short)
+    }
+
+    /// Finds all trips crossing a line, such as a cordon around some area, returning the person
+    /// and the index of the trip in their schedule. This uses the straight line between each
+    /// trip's endpoints, so trips routing around the ends of the line may be missed.
+    pub fn trips_crossing(&self, map: &Map, line: &PolyLine) -> Vec<(PersonID, usize)> {
+        let mut results = Vec::new();
+        for (person_idx, person) in self.people.iter().enumerate() {
+            for (trip_idx, trip) in person.trips.iter().enumerate() {
+                if let Ok(pl) = PolyLine::new(vec![trip.origin.pt(map), trip.destination.pt(map)]) {
+                    if line.intersection(&pl).is_some() {
+                        results.push((PersonID(person_idx), trip_idx));
+                    }
+                }
+            }
+        }
+        results
+    }
+
+    /// Without simulating, find the most cars parked at once, and when that happens. Cars are
+    /// assigned to trips the same way as during instantiation. Trips are assumed to take no time,
+    /// so this is an upper bound; in reality, some cars will still be driving.
+    pub fn peak_parking_demand(&self) -> (Time, usize) {
+        // The RNG only affects the vehicle specs, which don't matter here
+        let mut rng = Scenario::deterministic_rng();
+        // (time, +1 when a car parks, -1 when it leaves)
+        let mut changes: Vec<(Time, isize)> = Vec::new();
+        for person in &self.people {
+            let (_, cars_initially_parked_at, vehicle_foreach_trip) = person.get_vehicles(&mut rng);
+            for _ in cars_initially_parked_at {
+                changes.push((Time::START_OF_DAY, 1));
+            }
+            for (trip, maybe_idx) in person.trips.iter().zip(vehicle_foreach_trip) {
+                if trip.mode != TripMode::Drive || maybe_idx.is_none() {
+                    continue;
+                }
+                if let TripEndpoint::Bldg(_) = trip.origin {
+                    changes.push((trip.depart, -1));
+                }
+                if let TripEndpoint::Bldg(_) = trip.destination {
+                    changes.push((trip.depart, 1));
+                }
+            }
+        }
+        // At the same time, process cars leaving first
+        changes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap().then(a.1.cmp(&b.1)));
+
+        let mut current: isize = 0;
+        let mut peak = (Time::START_OF_DAY, 0);
+        for (time, delta) in changes {
+            current += delta;
+            if current > peak.1 as isize {
+                peak = (time, current as usize);
+            }
+        }
+        peak
+    }
+
+    /// Scale the number of trips for different purposes, like growing shopping trips by 20% with
+    /// a factor of 1.2. Each person's purpose is the purpose of their first trip not returning
+    /// home. To keep schedules valid, entire people are copied or removed, never individual trips
+    /// from the middle of a chain. So the counts are only approximate, and people with a grown
+    /// purpose also bring along their other trips. Purposes without a factor are unchanged.
+    pub fn grow_by_purpose(&mut self, factors: &BTreeMap<TripPurpose, f64>, rng: &mut XorShiftRng) {
+        let orig = self.people.len();
+        let mut people = Vec::new();
+        for person in self.people.drain(..) {
+            let purpose = person
+                .trips
+                .iter()
+                .find(|t| t.purpose != TripPurpose::Home)
+                .or_else(|| person.trips.first())
+                .map(|t| t.purpose);
+            let factor = match purpose.and_then(|p| factors.get(&p)) {
+                Some(factor) => factor.max(0.0),
+                None => {
+                    people.push(person);
+                    continue;
+                }
+            };
+            // Round the fractional part randomly, so the expected count matches the factor
+            let mut copies = factor.trunc() as usize;
+            if rng.gen_bool(factor.fract()) {
+                copies += 1;
+            }
+            for i in 0..copies {
+                let mut copy = person.clone();
+                // The first copy is the original person
+                if i > 0 {
+                    // The copy is somebody new, not a member of the original's household
+                    copy.household = None;
+                    for trip in &mut copy.trips {
+                        trip.modified = true;
+                    }
+                }
+                people.push(copy);
+            }
+        }
+        self.people = people;
+        info!(
+            "Growing trips by purpose changed {} people to {}",
+            prettyprint_usize(orig),
+            prettyprint_usize(self.people.len())
+        );
+    }
+
+    /// Writes a GeoJSON file with a straight line from the origin to the destination of every
+    /// trip, to quickly inspect in other GIS tools. Each line is tagged with the person, mode, and
+    /// purpose.
+    pub fn export_trip_lines_geojson(&self, map: &Map, path: &str) -> Result<()> {
+        let mut features = Vec::new();
+        for (idx, person) in self.people.iter().enumerate() {
+            for trip in &person.trips {
+                let line = match PolyLine::new(vec![trip.origin.pt(map), trip.destination.pt(map)])
+                {
+                    Ok(line) => line,
+                    // The endpoints are at the same spot
+                    Err(_) => continue,
+                };
+                let mut properties = geojson::JsonObject::new();
+                properties.insert("person".to_string(), idx.into());
+                properties.insert("mode".to_string(), format!("{:?}", trip.mode).into());
+                properties.insert("purpose".to_string(), trip.purpose.to_string().into());
+                features.push(geojson::Feature {
+                    bbox: None,
+                    geometry: Some(line.to_geojson(Some(map.get_gps_bounds()))),
+                    id: None,
+                    properties: Some(properties),
+                    foreign_members: None,
+                });
+            }
+        }
+        let geojson = geojson::GeoJson::from(geojson::FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        });
+        std::fs::write(path, geojson.to_string())?;
+        Ok(())
+    }
+
+    /// Writes every person's schedule as JSON, for a viewer that shows one row per person. Each
+    /// person has their index and ordered trips, with departure times in seconds after midnight.
+    pub fn export_schedules_json(&self, path: String) {
+        let schedules: Vec<PersonSchedule> = self
+            .people
+            .iter()
+            .enumerate()
+            .map(|(idx, person)| PersonSchedule {
+                person: idx,
+                trips: person
+                    .trips
+                    .iter()
+                    .map(|trip| ScheduledTrip {
+                        depart_seconds: trip.depart.inner_seconds(),
+                        mode: trip.mode,
+                        purpose: trip.purpose,
+                        from: trip.origin,
+                        to: trip.destination,
+                        cancelled: trip.cancelled,
+                    })
+                    .collect(),
+            })
+            .collect();
+        abstio::write_json(path, &schedules);
+    }
+
+    /// Only seed buses for routes that some transit trip in this scenario actually uses. This
+    /// speeds up instantiating scenarios covering a small part of a larger map. Returns the number
+    /// of routes kept.
+    pub fn prune_unused_bus_routes(&mut self, map: &Map) -> usize {
+        let mut routes = BTreeSet::new();
+        for trip in self.all_trips() {
+            if trip.mode != TripMode::Transit {
+                continue;
+            }
+            if let Ok(TripSpec::UsingTransit { route, .. }) = TripSpec::maybe_new(
+                trip.origin,
+                trip.destination,
+                TripMode::Transit,
+                None,
+                false,
+                false,
+                map,
+            ) {
+                routes.insert(map.get_br(route).full_name.clone());
+            }
+        }
+        let num_routes = routes.len();
+        self.only_seed_buses = Some(routes);
+        num_routes
+    }
+
+    /// Finds trips starting or ending at a border that can't serve the trip's mode, like a driving
+    /// trip starting at a border with only a footpath. These trips would be cancelled when they
+    /// start. Returns the person, the index of the trip in their schedule, and the problem.
+    pub fn find_unusable_border_trips(&self, map: &Map) -> Vec<(PersonID, usize, SpawnFailure)> {
+        let mut problems = Vec::new();
+        for (person_idx, person) in self.people.iter().enumerate() {
+            for (trip_idx, trip) in person.trips.iter().enumerate() {
+                if let Err(err) = trip
+                    .origin
+                    .check_border(trip.mode, true, map)
+                    .and_then(|_| trip.destination.check_border(trip.mode, false, map))
+                {
+                    problems.push((PersonID(person_idx), trip_idx, err));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Hashes the map, people, trips, and bus seeding, but not the scenario name. The order of
+    /// people doesn't matter, so two scenarios with the same demand hash equally. Useful for
+    /// skipping simulations of unchanged scenarios.
+    pub fn content_hash(&self) -> u64 {
+        // Floating point values like departure times can't be hashed directly, so hash the
+        // serialized form. This is stable across runs, so the result can be saved.
+        let mut people: Vec<u64> = self.people.iter().map(abstutil::stable_hash).collect();
+        people.sort_unstable();
+        abstutil::stable_hash(&(&self.map_name, people, &self.only_seed_buses, self.rng_seed))
+    }
+
+    /// Instead of letting trips that can't use their border be cancelled (see
+    /// `find_unusable_border_trips`), drop those trips and split the person there. The trips
+    /// before the bad one stay with the original person, and a new person with the same
+    /// attributes takes the trips after it. This keeps as much of the schedule valid as possible.
+    /// Returns the number of splits.
+    pub fn split_people_at_unusable_trips(&mut self, map: &Map) -> usize {
+        let mut splits = 0;
+        let mut people = Vec::new();
+        for person in self.people.drain(..) {
+            let mut piece = Vec::new();
+            let mut split = false;
+            for trip in &person.trips {
+                let usable = trip.origin.check_border(trip.mode, true, map).is_ok()
+                    && trip.destination.check_border(trip.mode, false, map).is_ok();
+                if usable {
+                    piece.push(trip.clone());
+                    continue;
+                }
+                split = true;
+                splits += 1;
+                if !piece.is_empty() {
+                    people.push(PersonSpec {
+                        orig_id: person.orig_id,
+                        wheelchair: person.wheelchair,
+                        household: person.household,
+                        trips: std::mem::take(&mut piece),
+                    });
+                }
+            }
+            if !split {
+                people.push(person);
+            } else if !piece.is_empty() {
+                people.push(PersonSpec {
+                    trips: piece,
+                    ..person
+                });
+            }
+        }
+        self.people = people;
+        if splits > 0 {
+            warn!(
+                "Split people at {} trips using unusable borders",
+                prettyprint_usize(splits)
+            );
+        }
+        splits
+    }
+
+    /// Some imported data covers multiple days, with departures past 24 hours. Split this into one
+    /// scenario per day, with departure times relative to the start of that day. Each person's
+    /// trips on one day become a person in that day's scenario, so `PersonID`s change. Days
+    /// without any trips produce an empty scenario. People with trips on multiple days are
+    /// reported, since their schedule might not connect between days.
+    pub fn split_by_day(&self) -> Vec<Scenario> {
+        let day_length = Duration::hours(24);
+        let mut days: Vec<Scenario> = Vec::new();
+        let mut span_days = 0;
+        for person in &self.people {
+            let mut per_day: BTreeMap<usize, Vec<IndividTrip>> = BTreeMap::new();
+            for trip in &person.trips {
+                let day = ((trip.depart - Time::START_OF_DAY) / day_length).floor() as usize;
+                let mut trip = trip.clone();
+                trip.depart = trip.depart - day_length * (day as f64);
+                per_day.entry(day).or_insert_with(Vec::new).push(trip);
+            }
+            if per_day.len() > 1 {
+                span_days += 1;
+            }
+
+            for (day, trips) in per_day {
+                while days.len() <= day {
+                    days.push(Scenario {
+                        scenario_name: format!("{} day {}", self.scenario_name, days.len() + 1),
+                        map_name: self.map_name.clone(),
+                        people: Vec::new(),
+                        only_seed_buses: self.only_seed_buses.clone(),
+                        rng_seed: self.rng_seed,
+                    });
+                }
+                days[day].people.push(PersonSpec {
+                    orig_id: person.orig_id,
+                    wheelchair: person.wheelchair,
+                    household: person.household,
+                    trips,
+                });
+            }
+        }
+        if span_days > 0 {
+            warn!(
+                "{} people have trips on more than one day",
+                prettyprint_usize(span_days)
+            );
+        }
+        days
+    }
+
+    /// Only keep trips departing in `[start, end)`, such as for simulating just the peak hours.
+    /// People left without trips are removed, so `PersonID`s change. If the remaining trips of a
+    /// person don't chain together, the person is split there, so everybody still passes
+    /// `check_schedule`. Returns the number of trips removed.
+    pub fn clip_time_window(&mut self, start: Time, end: Time) -> usize {
+        let mut removed = 0;
+        let mut people = Vec::new();
+        for person in self.people.drain(..) {
+            let orig_trips = person.trips.len();
+            let mut pieces: Vec<Vec<IndividTrip>> = Vec::new();
+            for trip in person.trips {
+                if trip.depart < start || trip.depart >= end {
+                    continue;
+                }
+                let continues = match pieces.last().and_then(|piece| piece.last()) {
+                    Some(prev) => {
+                        prev.destination == trip.origin
+                            || (matches!(prev.destination, TripEndpoint::Border(_))
+                                && matches!(trip.origin, TripEndpoint::Border(_)))
+                    }
+                    None => false,
+                };
+                if continues {
+                    pieces.last_mut().unwrap().push(trip);
+                } else {
+                    pieces.push(vec![trip]);
+                }
+            }
+
+            removed += orig_trips - pieces.iter().map(|piece| piece.len()).sum::<usize>();
+            for trips in pieces {
+                people.push(PersonSpec {
+                    orig_id: person.orig_id,
+                    wheelchair: person.wheelchair,
+                    household: person.household,
+                    trips,
+                });
+            }
+        }
+        self.people = people;
+        removed
+    }
+
+    /// Label driving trips by the corridor they use. Each corridor is a name and a set of roads,
+    /// usually major arterials. Each driving trip is routed, and gets the corridor covering the
+    /// most distance of its path, if any. Returns the number of trips labeled.
+    pub fn assign_corridors(
+        &mut self,
+        map: &Map,
+        corridors: &[(String, BTreeSet<RoadID>)],
+    ) -> usize {
+        let mut labeled = 0;
+        for trip in self.people.iter_mut().flat_map(|p| p.trips.iter_mut()) {
+            if trip.mode != TripMode::Drive {
+                continue;
+            }
+            let path = match TripEndpoint::path_req(trip.origin, trip.destination, trip.mode, map)
+                .and_then(|req| map.pathfind(req).ok())
+            {
+                Some(path) => path,
+                None => continue,
+            };
+            let mut dist_per_corridor = vec![Distance::ZERO; corridors.len()];
+            for step in path.get_steps() {
+                if let PathStep::Lane(l) = step {
+                    for (idx, (_, roads)) in corridors.iter().enumerate() {
+                        if roads.contains(&l.road) {
+                            dist_per_corridor[idx] += map.get_l(*l).length();
+                        }
+                    }
+                }
+            }
+            if let Some((idx, _)) = dist_per_corridor
+                .into_iter()
+                .enumerate()
+                .filter(|(_, dist)| *dist > Distance::ZERO)
+                .max_by_key(|(_, dist)| *dist)
+            {
+                trip.corridor = Some(corridors[idx].0.clone());
+                labeled += 1;
+            }
+        }
+        labeled
+    }
+
+    /// Without simulating, estimate how many trips board each transit route. Each transit trip
+    /// that isn't cancelled picks a route the same way as when the trip starts. Trips with no
+    /// useful route will just walk, so they aren't counted.
+    pub fn transit_ridership(&self, map: &Map) -> Counter<BusRouteID> {
+        let mut ridership = Counter::new();
+        for trip in self.all_trips() {
+            if trip.mode != TripMode::Transit || trip.cancelled {
+                continue;
+            }
+            if let Ok(TripSpec::UsingTransit { route, .. }) = TripSpec::maybe_new(
+                trip.origin,
+                trip.destination,
+                TripMode::Transit,
+                None,
+                false,
+                false,
+                map,
+            ) {
+                ridership.inc(route);
+            }
+        }
+        ridership
+    }
+
+    /// Counts trips between zones. `result[i][j]` is the number of trips starting in zone `i` and
+    /// ending in zone `j`. Endpoints outside every zone use an extra catch-all zone, with index
+    /// `zones.len()`. If zones overlap, the first one containing an endpoint wins. Cancelled trips
+    /// are skipped.
+    pub fn od_matrix(&self, map: &Map, zones: &[Polygon]) -> Vec<Vec<usize>> {
+        // Many trips share endpoints, so only look up each one once
+        let mut zone_per_endpoint: BTreeMap<TripEndpoint, usize> = BTreeMap::new();
+        let mut find_zone = |endpoint: TripEndpoint| -> usize {
+            *zone_per_endpoint.entry(endpoint).or_insert_with(|| {
+                let pt = endpoint.pt(map);
+                zones
+                    .iter()
+                    .position(|zone| zone.contains_pt(pt))
+                    .unwrap_or(zones.len())
+            })
+        };
+
+        let mut matrix = vec![vec![0; zones.len() + 1]; zones.len() + 1];
+        for trip in self.all_trips().filter(|t| !t.cancelled) {
+            let from = find_zone(trip.origin);
+            let to = find_zone(trip.destination);
+            matrix[from][to] += 1;
+        }
+        matrix
+    }
+
+    /// Returns the fraction of non-cancelled trips using each mode, from 0 to 1. Modes without any
+    /// trips are omitted, so if every trip is cancelled, the result is empty.
+    pub fn mode_share(&self) -> BTreeMap<TripMode, f64> {
+        let mut counts: Counter<TripMode> = Counter::new();
+        for trip in self.all_trips().filter(|t| !t.cancelled) {
+            counts.inc(trip.mode);
+        }
+        let total = counts.sum() as f64;
+        counts
+            .consume()
+            .into_iter()
+            .map(|(mode, cnt)| (mode, (cnt as f64) / total))
+            .collect()
+    }
+
     pub fn all_trips(&self) -> impl Iterator<Item = &IndividTrip> {
         self.people.iter().flat_map(|p| p.trips.iter())
     }
 }
 
+/// Cancel any trips using one of these cars, returning the number cancelled. Used when the cars
+/// never get seeded.
+fn cancel_trips_using_cars(
+    schedule_trips: &mut [(PersonID, TripInfo, StartTripArgs, Option<SpawnFailure>)],
+    cars: &BTreeSet<CarID>,
+) -> usize {
+    let mut num_cancelled = 0;
+    for (_, info, args, failure) in schedule_trips {
+        if info.cancellation_reason.is_some() || failure.is_some() {
+            continue;
+        }
+        if let Some(car) = args.use_vehicle {
+            if cars.contains(&car) {
+                info.cancellation_reason = Some(format!("{} was never parked", car));
+                num_cancelled += 1;
+            }
+        }
+    }
+    num_cancelled
+}
+
+/// Cancel any trips that would exceed the maximum number of concurrent trips for their mode,
+/// returning the number cancelled. Earlier trips get priority.
+fn cap_concurrent_trips(
+    schedule_trips: &mut [(PersonID, TripInfo, StartTripArgs, Option<SpawnFailure>)],
+    caps: &BTreeMap<TripMode, usize>,
+    map: &Map,
+) -> usize {
+    let mut order: Vec<usize> = (0..schedule_trips.len()).collect();
+    order.sort_by_key(|idx| schedule_trips[*idx].1.departure);
+
+    // Per mode, when will each ongoing trip finish?
+    let mut ongoing: BTreeMap<TripMode, Vec<Time>> = BTreeMap::new();
+    let mut num_capped = 0;
+    for idx in order {
+        let (_, info, _, failure) = &mut schedule_trips[idx];
+        if info.cancellation_reason.is_some() {
+            continue;
+        }
+        let cap = if let Some(cap) = caps.get(&info.mode) {
+            *cap
+        } else {
+            continue;
+        };
+        let finish_times = ongoing.entry(info.mode).or_insert_with(Vec::new);
+        let now = info.departure;
+        finish_times.retain(|t| *t > now);
+        if finish_times.len() >= cap {
+            *failure = Some(SpawnFailure::new(
+                SpawnFailureKind::OverCapacity,
+                format!(
+                    "more than {} {} trips happening at once",
+                    cap,
+                    info.mode.noun()
+                ),
+            ));
+            num_capped += 1;
+        } else {
+            finish_times.push(now + estimate_trip_duration(info, map));
+        }
+    }
+    num_capped
+}
+
+/// Without simulating, guess how long a trip takes by traveling in a straight line at a typical
+/// speed for the mode.
+fn estimate_trip_duration(info: &TripInfo, map: &Map) -> Duration {
+    estimate_duration(info.mode, info.start, info.end, map)
+}
+
+fn estimate_duration(mode: TripMode, from: TripEndpoint, to: TripEndpoint, map: &Map) -> Duration {
+    let speed = match mode {
+        TripMode::Walk | TripMode::Transit => map_model::MAX_WALKING_SPEED,
+        TripMode::Bike => map_model::MAX_BIKE_SPEED,
+        TripMode::Drive => Speed::miles_per_hour(25.0),
+    };
+    from.pt(map).dist_to(to.pt(map)) / speed
+}
+
 fn seed_parked_cars(
     parked_cars: Vec<(Vehicle, BuildingID)>,
     sim: &mut Sim,
@@ -413,7 +1991,16 @@ impl PersonSpec {
         }
 
         for pair in self.trips.windows(2) {
-            if pair[0].depart >= pair[1].depart {
+            if pair[0].depart == pair[1].depart {
+                bail!(
+                    "Person ({:?}) starts two trips at the same time {}: {:?} and {:?}",
+                    self.orig_id,
+                    pair[0].depart,
+                    pair[0],
+                    pair[1]
+                );
+            }
+            if pair[0].depart > pair[1].depart {
                 bail!(
                     "Person ({:?}) starts two trips in the wrong order: {} then {}",
                     self.orig_id,
@@ -448,9 +2035,71 @@ impl PersonSpec {
             }
         }
 
+        // A trip pinned to a car must start where that car was left
+        let mut car_left_at: BTreeMap<usize, TripEndpoint> = BTreeMap::new();
+        for trip in &self.trips {
+            let label = match trip.use_car {
+                Some(label) if trip.mode == TripMode::Drive => label,
+                _ => continue,
+            };
+            let ok = match car_left_at.get(&label) {
+                Some(TripEndpoint::Bldg(b)) => trip.origin == TripEndpoint::Bldg(*b),
+                // The car left the map, so it can only come back at a border
+                Some(_) => !matches!(trip.origin, TripEndpoint::Bldg(_)),
+                None => true,
+            };
+            if !ok {
+                bail!(
+                    "Person ({:?}) wants car {} at {:?}, but it was left at {:?}",
+                    self.orig_id,
+                    label,
+                    trip.origin,
+                    car_left_at[&label]
+                );
+            }
+            car_left_at.insert(label, trip.destination);
+        }
+
         Ok(())
     }
 
+    /// Returns the number of trips, the first and last departure time, and all modes used. If the
+    /// person has no trips, both times are midnight.
+    pub fn summary(&self) -> (usize, Time, Time, BTreeSet<TripMode>) {
+        let first_depart = self
+            .trips
+            .first()
+            .map(|t| t.depart)
+            .unwrap_or(Time::START_OF_DAY);
+        let last_depart = self
+            .trips
+            .last()
+            .map(|t| t.depart)
+            .unwrap_or(Time::START_OF_DAY);
+        let modes = self.trips.iter().map(|t| t.mode).collect();
+        (self.trips.len(), first_depart, last_depart, modes)
+    }
+
+    /// A hash identifying this person, the same every run, so visualizations can consistently
+    /// color them. Based on `orig_id` when present, since `PersonID`s change when a scenario is
+    /// filtered, and otherwise on `id`, which should be this person's position in the scenario.
+    /// See `map_gui::colors::ColorScheme::person_color`.
+    pub fn stable_hash(&self, id: PersonID) -> u64 {
+        match self.orig_id {
+            Some(orig_id) => abstutil::stable_hash(&orig_id),
+            None => abstutil::stable_hash(&id),
+        }
+    }
+
+    /// Collapse consecutive trips that are exactly the same, including whether they were
+    /// cancelled or modified. A cancelled copy of a trip isn't a duplicate of the original. Returns
+    /// the number of trips removed.
+    pub fn dedup_simultaneous_trips(&mut self) -> usize {
+        let orig = self.trips.len();
+        self.trips.dedup();
+        orig - self.trips.len()
+    }
+
     fn get_vehicles(
         &self,
         rng: &mut XorShiftRng,
@@ -466,6 +2115,8 @@ impl PersonSpec {
         let mut bike_idx = None;
         // For each indexed car, is it parked somewhere, or off-map?
         let mut car_locations: Vec<(usize, Option<BuildingID>)> = Vec::new();
+        // From IndividTrip::use_car to the indexed car
+        let mut pinned_cars: BTreeMap<usize, usize> = BTreeMap::new();
 
         // TODO If the trip is cancelled, this should be affected...
         for trip in &self.trips {
@@ -484,22 +2135,38 @@ impl PersonSpec {
                         _ => None,
                     };
 
-                    // Any available cars in the right spot?
-                    let idx = if let Some(idx) = car_locations
-                        .iter()
-                        .find(|(_, parked_at)| *parked_at == need_parked_at)
-                        .map(|(idx, _)| *idx)
-                    {
-                        idx
-                    } else {
-                        // Need a new car, starting in the right spot
-                        let idx = vehicle_specs.len();
-                        vehicle_specs.push(Scenario::rand_car(rng));
-                        if let Some(b) = need_parked_at {
-                            cars_initially_parked_at.push((idx, b));
+                    let pinned = trip
+                        .use_car
+                        .and_then(|label| pinned_cars.get(&label).cloned());
+                    let idx = match pinned {
+                        // check_schedule makes sure the car was left where this trip starts
+                        Some(idx) => idx,
+                        None => {
+                            // Any available cars in the right spot? Cars pinned to a label aren't
+                            // available to other trips.
+                            if let Some(idx) = car_locations
+                                .iter()
+                                .find(|(idx, parked_at)| {
+                                    *parked_at == need_parked_at
+                                        && !pinned_cars.values().any(|pinned| pinned == idx)
+                                })
+                                .map(|(idx, _)| *idx)
+                            {
+                                idx
+                            } else {
+                                // Need a new car, starting in the right spot
+                                let idx = vehicle_specs.len();
+                                vehicle_specs.push(Scenario::rand_car(rng));
+                                if let Some(b) = need_parked_at {
+                                    cars_initially_parked_at.push((idx, b));
+                                }
+                                idx
+                            }
                         }
-                        idx
                     };
+                    if let Some(label) = trip.use_car {
+                        pinned_cars.insert(label, idx);
+                    }
 
                     // Where does this car wind up?
                     car_locations.retain(|(i, _)| idx != *i);
@@ -536,3 +2203,238 @@ impl PersonSpec {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use map_model::IntersectionID;
+
+    fn scenario(households: Vec<Option<usize>>) -> Scenario {
+        Scenario {
+            scenario_name: "test".to_string(),
+            map_name: MapName::seattle("test"),
+            people: households
+                .into_iter()
+                .enumerate()
+                .map(|(idx, household)| PersonSpec {
+                    orig_id: Some(OrigPersonID(0, idx)),
+                    wheelchair: false,
+                    household,
+                    trips: vec![IndividTrip::new(
+                        Time::START_OF_DAY + Duration::hours(7),
+                        TripPurpose::Work,
+                        TripEndpoint::Border(IntersectionID(0)),
+                        TripEndpoint::Border(IntersectionID(1)),
+                        TripMode::Drive,
+                    )],
+                })
+                .collect(),
+            only_seed_buses: None,
+            rng_seed: None,
+        }
+    }
+
+    #[test]
+    fn test_shard_and_merge_keep_households_together() {
+        let households = vec![
+            Some(0),
+            None,
+            Some(1),
+            Some(0),
+            Some(2),
+            None,
+            Some(1),
+            Some(0),
+        ];
+        let original = scenario(households.clone());
+
+        let shards = original.shard(3);
+        assert_eq!(
+            shards.iter().map(|s| s.people.len()).sum::<usize>(),
+            households.len()
+        );
+        for h in 0..3 {
+            let shards_with_household = shards
+                .iter()
+                .filter(|s| s.people.iter().any(|p| p.household == Some(h)))
+                .count();
+            assert_eq!(shards_with_household, 1, "household {} was split", h);
+        }
+
+        let merged = Scenario::merge(shards).unwrap();
+        assert_eq!(merged.people.len(), households.len());
+        // From the original household to the merged one
+        let mut renumbered: BTreeMap<usize, usize> = BTreeMap::new();
+        for person in &merged.people {
+            let orig = households[person.orig_id.unwrap().1];
+            assert_eq!(orig.is_some(), person.household.is_some());
+            if let (Some(orig), Some(new)) = (orig, person.household) {
+                assert_eq!(*renumbered.entry(orig).or_insert(new), new);
+            }
+        }
+        // Different households didn't get merged together
+        let distinct: BTreeSet<usize> = renumbered.values().cloned().collect();
+        assert_eq!(distinct.len(), renumbered.len());
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let mut original = scenario(vec![Some(3), None]);
+        original.people[0].wheelchair = true;
+        original.rng_seed = Some(42);
+
+        let copy: Scenario = abstutil::from_binary(&abstutil::to_binary(&original)).unwrap();
+        assert_eq!(copy.rng_seed, Some(42));
+        assert_eq!(copy.people.len(), 2);
+        for (before, after) in original.people.iter().zip(copy.people.iter()) {
+            assert_eq!(before.orig_id, after.orig_id);
+            assert_eq!(before.wheelchair, after.wheelchair);
+            assert_eq!(before.household, after.household);
+            assert_eq!(before.trips, after.trips);
+        }
+    }
+
+    #[test]
+    fn test_modifiers_removing_trips() {
+        let mut s = scenario(vec![None, None]);
+        for person in &mut s.people {
+            person.trips[0].modified = true;
+        }
+        let (before, after) = s.apply_modifiers(&Map::blank(), &[ScenarioModifier::RepeatDays(0)]);
+        assert_eq!((before, after), (2, 0));
+        assert_eq!(s.all_trips().count(), 0);
+    }
+
+    #[test]
+    fn test_trips_needing_unseeded_cars_are_cancelled() {
+        let car = CarID {
+            id: 0,
+            vehicle_type: VehicleType::Car,
+        };
+        let trip = |mode, use_vehicle| {
+            (
+                PersonID(0),
+                TripInfo {
+                    departure: Time::START_OF_DAY,
+                    mode,
+                    start: TripEndpoint::Bldg(BuildingID(0)),
+                    end: TripEndpoint::Bldg(BuildingID(1)),
+                    purpose: TripPurpose::Work,
+                    modified: false,
+                    cancellation_reason: None,
+                },
+                StartTripArgs {
+                    retry_if_no_room: true,
+                    use_vehicle,
+                    max_start_delay: None,
+                },
+                None,
+            )
+        };
+        let mut schedule_trips = vec![
+            trip(TripMode::Drive, Some(car)),
+            trip(TripMode::Walk, None),
+            trip(TripMode::Drive, Some(car)),
+        ];
+
+        let cars: BTreeSet<CarID> = vec![car].into_iter().collect();
+        assert_eq!(cancel_trips_using_cars(&mut schedule_trips, &cars), 2);
+        let cancelled: Vec<bool> = schedule_trips
+            .iter()
+            .map(|(_, info, _, _)| info.cancellation_reason.is_some())
+            .collect();
+        assert_eq!(cancelled, vec![true, false, true]);
+        // These are ordinary cancellations, not spawn failures
+        assert!(schedule_trips
+            .iter()
+            .all(|(_, _, _, failure)| failure.is_none()));
+    }
+
+    #[test]
+    fn test_peak_profile_never_ties() {
+        let mut s = scenario(vec![None; 5]);
+        for person in &mut s.people {
+            let trip = person.trips[0].clone();
+            let mut back = trip.clone();
+            back.origin = trip.destination;
+            back.destination = trip.origin;
+            person.trips = vec![trip.clone(), back, trip];
+        }
+
+        // With a tiny spread around midnight, almost every sample gets clamped to 0
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        s.apply_peak_profile(
+            Time::START_OF_DAY,
+            Time::START_OF_DAY,
+            Duration::seconds(0.001),
+            &mut rng,
+        );
+        for person in &s.people {
+            person.check_schedule().unwrap();
+        }
+    }
+
+    fn drive(hour: f64, from: usize, to: usize, use_car: Option<usize>) -> IndividTrip {
+        let mut trip = IndividTrip::new(
+            Time::START_OF_DAY + Duration::hours(hour),
+            TripPurpose::Work,
+            TripEndpoint::Bldg(BuildingID(from)),
+            TripEndpoint::Bldg(BuildingID(to)),
+            TripMode::Drive,
+        );
+        trip.use_car = use_car;
+        trip
+    }
+
+    #[test]
+    fn test_new_car_label_gets_its_own_car() {
+        let mut person = scenario(vec![None]).people.pop().unwrap();
+        // The first car is left at building 2, where the second trip starts. It's pinned to
+        // another label, so the second trip needs a different car.
+        person.trips = vec![drive(7.0, 1, 2, Some(0)), drive(8.0, 2, 3, Some(1))];
+        person.check_schedule().unwrap();
+
+        let (specs, parked_at, foreach_trip) =
+            person.get_vehicles(&mut XorShiftRng::seed_from_u64(42));
+        assert_eq!(specs.len(), 2);
+        assert_eq!(parked_at, vec![(0, BuildingID(1)), (1, BuildingID(2))]);
+        assert_eq!(foreach_trip, vec![Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn test_pinned_car_must_be_at_origin() {
+        let mut person = scenario(vec![None]).people.pop().unwrap();
+        let mut walk = drive(8.0, 2, 3, None);
+        walk.mode = TripMode::Walk;
+        // The car is left at building 2, but the last trip wants it at building 3
+        person.trips = vec![drive(7.0, 1, 2, Some(0)), walk, drive(9.0, 3, 1, Some(0))];
+        assert!(person.check_schedule().is_err());
+    }
+
+    #[test]
+    fn test_dedup_simultaneous_trips() {
+        let mut person = scenario(vec![None]).people.pop().unwrap();
+        let mut cancelled = drive(8.0, 2, 1, None);
+        cancelled.cancelled = true;
+        person.trips = vec![
+            drive(7.0, 1, 2, None),
+            drive(7.0, 1, 2, None),
+            drive(8.0, 2, 1, None),
+            cancelled,
+        ];
+        assert!(person.check_schedule().is_err());
+
+        // The cancelled copy stays
+        assert_eq!(person.dedup_simultaneous_trips(), 1);
+        assert_eq!(person.trips.len(), 3);
+        assert!(person.trips[2].cancelled);
+    }
+
+    #[test]
+    fn test_stable_hash_is_pinned() {
+        // Colors and saved hashes depend on this, so it must never change
+        let mut person = scenario(vec![None]).people.pop().unwrap();
+        person.orig_id = Some(OrigPersonID(1, 2));
+        assert_eq!(person.stable_hash(PersonID(0)), 0xc9c2_8939_c996_68c6);
+    }
+}
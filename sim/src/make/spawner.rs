@@ -1,10 +1,11 @@
 //! Intermediate structures used to instantiate a Scenario. Badly needs simplification:
 //! https://github.com/a-b-street/abstreet/issues/258
 
-use anyhow::Result;
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
-use geom::Pt2D;
+use geom::{Duration, Pt2D};
 use map_model::{
     BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
 };
@@ -17,6 +18,7 @@ use crate::{CarID, DrivingGoal, SidewalkSpot, TripLeg, TripMode, VehicleType, SP
 pub(crate) struct StartTripArgs {
     pub retry_if_no_room: bool,
     pub use_vehicle: Option<CarID>,
+    pub max_start_delay: Option<Duration>,
 }
 
 // TODO Some of these fields are unused now that we separately pass TripEndpoint
@@ -33,7 +35,7 @@ pub(crate) enum TripSpec {
     /// Something went wrong spawning the trip.
     SpawningFailure {
         use_vehicle: Option<CarID>,
-        error: String,
+        error: SpawnFailure,
     },
     UsingParkedCar {
         /// This must be a currently parked vehicle owned by the person.
@@ -44,11 +46,15 @@ pub(crate) enum TripSpec {
     JustWalking {
         start: SidewalkSpot,
         goal: SidewalkSpot,
+        /// Not used by pathfinding yet, but eventually this should avoid stairs and steep paths.
+        wheelchair: bool,
     },
     UsingBike {
         bike: CarID,
         start: BuildingID,
         goal: DrivingGoal,
+        /// Only used if the trip falls back to walking
+        wheelchair: bool,
     },
     UsingTransit {
         start: SidewalkSpot,
@@ -59,6 +65,43 @@ pub(crate) enum TripSpec {
     },
 }
 
+/// Why a trip couldn't be spawned
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum SpawnFailureKind {
+    /// There's no way to start this trip's mode at a border
+    CantStartAtBorder,
+    /// There's no way to end this trip's mode at a border
+    CantEndAtBorder,
+    /// A vehicle appearing somewhere has no valid goal position
+    NoGoalPosition,
+    /// A bike trip can't start biking, and can't fall back to walking either
+    CantBikeOrWalk,
+    /// The previous trip ran late, so this one couldn't start within its maximum delay
+    WaitedTooLong,
+    /// Too many trips of this mode were already happening at once. See
+    /// `InstantiateOptions::max_concurrent_trips`.
+    OverCapacity,
+}
+
+/// A categorized reason for a trip failing to spawn, with details for logging.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SpawnFailure {
+    pub kind: SpawnFailureKind,
+    pub details: String,
+}
+
+impl SpawnFailure {
+    pub(crate) fn new(kind: SpawnFailureKind, details: String) -> SpawnFailure {
+        SpawnFailure { kind, details }
+    }
+}
+
+impl fmt::Display for SpawnFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
 impl TripSpec {
     pub fn into_plan(self, map: &Map) -> (TripSpec, Vec<TripLeg>) {
         // TODO We'll want to repeat this validation when we spawn stuff later for a second leg...
@@ -98,7 +141,14 @@ impl TripSpec {
                 if goal.goal_pos(constraints, map).is_none() {
                     return TripSpec::SpawningFailure {
                         use_vehicle: Some(*use_vehicle),
-                        error: format!("goal_pos to {:?} for a {:?} failed", goal, constraints),
+                        error: SpawnFailure::new(
+                            SpawnFailureKind::NoGoalPosition,
+                            format!(
+                                "goal_pos for a {:?} failed; the goal is to {}",
+                                constraints,
+                                goal.describe(map)
+                            ),
+                        ),
                     }
                     .into_plan(map);
                 }
@@ -128,17 +178,24 @@ impl TripSpec {
                 }
                 legs.push(TripLeg::Walk(goal.clone()));
             }
-            TripSpec::UsingBike { start, goal, bike } => {
+            TripSpec::UsingBike {
+                start,
+                goal,
+                bike,
+                wheelchair,
+            } => {
                 // TODO Might not be possible to walk to the same border if there's no sidewalk
                 let backup_plan = match goal {
                     DrivingGoal::ParkNear(b) => Some(TripSpec::JustWalking {
                         start: SidewalkSpot::building(*start, map),
                         goal: SidewalkSpot::building(*b, map),
+                        wheelchair: *wheelchair,
                     }),
                     DrivingGoal::Border(i, _) => {
                         SidewalkSpot::end_at_border(*i, map).map(|goal| TripSpec::JustWalking {
                             start: SidewalkSpot::building(*start, map),
                             goal,
+                            wheelchair: *wheelchair,
                         })
                     }
                 };
@@ -177,9 +234,14 @@ impl TripSpec {
                 } else {
                     return TripSpec::SpawningFailure {
                         use_vehicle: Some(*bike),
-                        error: format!(
-                            "Can't start biking from {} and can't walk either! Goal is {:?}",
-                            start, goal
+                        error: SpawnFailure::new(
+                            SpawnFailureKind::CantBikeOrWalk,
+                            format!(
+                                "Can't start biking from {} and can't walk either! The goal is to \
+                                 {}",
+                                start,
+                                goal.describe(map)
+                            ),
                         ),
                     }
                     .into_plan(map);
@@ -216,8 +278,9 @@ impl TripSpec {
         mode: TripMode,
         use_vehicle: Option<CarID>,
         retry_if_no_room: bool,
+        wheelchair: bool,
         map: &Map,
-    ) -> Result<TripSpec> {
+    ) -> Result<TripSpec, SpawnFailure> {
         Ok(match mode {
             TripMode::Drive | TripMode::Bike => {
                 let constraints = if mode == TripMode::Drive {
@@ -239,6 +302,7 @@ impl TripSpec {
                                 start: start_bldg,
                                 goal,
                                 bike: use_vehicle.unwrap(),
+                                wheelchair,
                             }
                         }
                     }
@@ -250,7 +314,14 @@ impl TripSpec {
                             // pick the least loaded lane or similar.
                             .and_then(|dr| dr.lanes(constraints, map).pop())
                             .ok_or_else(|| {
-                                anyhow!("can't start a {} trip from {}", mode.ongoing_verb(), i)
+                                SpawnFailure::new(
+                                    SpawnFailureKind::CantStartAtBorder,
+                                    format!(
+                                        "can't start a {} trip from {}",
+                                        mode.ongoing_verb(),
+                                        i
+                                    ),
+                                )
                             })?;
                         TripSpec::VehicleAppearing {
                             start_pos: Position::new(start_lane, SPAWN_DIST),
@@ -270,6 +341,7 @@ impl TripSpec {
             TripMode::Walk => TripSpec::JustWalking {
                 start: from.start_sidewalk_spot(map)?,
                 goal: to.end_sidewalk_spot(map)?,
+                wheelchair,
             },
             TripMode::Transit => {
                 let start = from.start_sidewalk_spot(map)?;
@@ -287,7 +359,11 @@ impl TripSpec {
                 } else {
                     //warn!("{:?} not actually using transit, because pathfinding didn't find any
                     // useful route", trip);
-                    TripSpec::JustWalking { start, goal }
+                    TripSpec::JustWalking {
+                        start,
+                        goal,
+                        wheelchair,
+                    }
                 }
             }
         })
@@ -330,25 +406,71 @@ impl TripEndpoint {
         })
     }
 
-    fn start_sidewalk_spot(&self, map: &Map) -> Result<SidewalkSpot> {
+    /// If this is a border, check that a trip of some mode can start or end here.
+    pub(crate) fn check_border(
+        self,
+        mode: TripMode,
+        start: bool,
+        map: &Map,
+    ) -> Result<(), SpawnFailure> {
+        let i = match self {
+            TripEndpoint::Border(i) => i,
+            _ => return Ok(()),
+        };
+        let constraints = match mode {
+            TripMode::Walk | TripMode::Transit => {
+                return if start {
+                    self.start_sidewalk_spot(map).map(|_| ())
+                } else {
+                    self.end_sidewalk_spot(map).map(|_| ())
+                };
+            }
+            TripMode::Drive => PathConstraints::Car,
+            TripMode::Bike => PathConstraints::Bike,
+        };
+        if !start {
+            return self.driving_goal(constraints, map).map(|_| ());
+        }
+        match map.get_i(i).some_outgoing_road(map) {
+            Some(dr) if !dr.lanes(constraints, map).is_empty() => Ok(()),
+            _ => Err(SpawnFailure::new(
+                SpawnFailureKind::CantStartAtBorder,
+                format!("can't start a {} trip from {}", mode.ongoing_verb(), i),
+            )),
+        }
+    }
+
+    fn start_sidewalk_spot(&self, map: &Map) -> Result<SidewalkSpot, SpawnFailure> {
         match self {
             TripEndpoint::Bldg(b) => Ok(SidewalkSpot::building(*b, map)),
-            TripEndpoint::Border(i) => SidewalkSpot::start_at_border(*i, map)
-                .ok_or_else(|| anyhow!("can't start walking from {}", i)),
+            TripEndpoint::Border(i) => SidewalkSpot::start_at_border(*i, map).ok_or_else(|| {
+                SpawnFailure::new(
+                    SpawnFailureKind::CantStartAtBorder,
+                    format!("can't start walking from {}", i),
+                )
+            }),
             TripEndpoint::SuddenlyAppear(pos) => Ok(SidewalkSpot::suddenly_appear(*pos, map)),
         }
     }
 
-    fn end_sidewalk_spot(&self, map: &Map) -> Result<SidewalkSpot> {
+    fn end_sidewalk_spot(&self, map: &Map) -> Result<SidewalkSpot, SpawnFailure> {
         match self {
             TripEndpoint::Bldg(b) => Ok(SidewalkSpot::building(*b, map)),
-            TripEndpoint::Border(i) => SidewalkSpot::end_at_border(*i, map)
-                .ok_or_else(|| anyhow!("can't end walking at {}", i)),
+            TripEndpoint::Border(i) => SidewalkSpot::end_at_border(*i, map).ok_or_else(|| {
+                SpawnFailure::new(
+                    SpawnFailureKind::CantEndAtBorder,
+                    format!("can't end walking at {}", i),
+                )
+            }),
             TripEndpoint::SuddenlyAppear(_) => unreachable!(),
         }
     }
 
-    fn driving_goal(&self, constraints: PathConstraints, map: &Map) -> Result<DrivingGoal> {
+    fn driving_goal(
+        &self,
+        constraints: PathConstraints,
+        map: &Map,
+    ) -> Result<DrivingGoal, SpawnFailure> {
         match self {
             TripEndpoint::Bldg(b) => Ok(DrivingGoal::ParkNear(*b)),
             TripEndpoint::Border(i) => map
@@ -363,7 +485,12 @@ impl TripEndpoint {
                         Some(DrivingGoal::Border(dr.dst_i(map), lanes[0]))
                     }
                 })
-                .ok_or_else(|| anyhow!("can't end at {} for {:?}", i, constraints)),
+                .ok_or_else(|| {
+                    SpawnFailure::new(
+                        SpawnFailureKind::CantEndAtBorder,
+                        format!("can't end at {} for {:?}", i, constraints),
+                    )
+                }),
             TripEndpoint::SuddenlyAppear(_) => unreachable!(),
         }
     }
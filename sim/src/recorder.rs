@@ -70,6 +70,8 @@ impl TrafficRecorder {
         for trip in self.trips.drain(..) {
             people.push(PersonSpec {
                 orig_id: None,
+                wheelchair: false,
+                household: None,
                 trips: vec![trip],
             });
         }
@@ -78,6 +80,7 @@ impl TrafficRecorder {
             map_name: map.get_name().clone(),
             people,
             only_seed_buses: None,
+            rng_seed: None,
         }
         .save();
     }
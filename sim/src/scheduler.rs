@@ -27,6 +27,8 @@ pub(crate) enum Command {
     Pandemic(pandemic::Cmd),
     /// The Time is redundant, just used to dedupe commands
     StartBus(BusRouteID, Time),
+    /// Give up on a delayed trip that still hasn't started by its max_start_delay
+    ExpireDelayedTrip(TripID),
 }
 
 impl Command {
@@ -50,6 +52,7 @@ impl Command {
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::StartBus(r, t) => CommandType::StartBus(*r, *t),
+            Command::ExpireDelayedTrip(id) => CommandType::ExpireDelayedTrip(*id),
         }
     }
 
@@ -65,6 +68,7 @@ impl Command {
             Command::Callback(_) => SimpleCommandType::Callback,
             Command::Pandemic(_) => SimpleCommandType::Pandemic,
             Command::StartBus(_, _) => SimpleCommandType::StartBus,
+            Command::ExpireDelayedTrip(_) => SimpleCommandType::ExpireDelayedTrip,
         }
     }
 }
@@ -81,6 +85,7 @@ enum CommandType {
     Callback,
     Pandemic(pandemic::Cmd),
     StartBus(BusRouteID, Time),
+    ExpireDelayedTrip(TripID),
 }
 
 /// A more compressed form of CommandType, just used for keeping stats on event processing.
@@ -94,6 +99,7 @@ enum SimpleCommandType {
     Callback,
     Pandemic,
     StartBus,
+    ExpireDelayedTrip,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
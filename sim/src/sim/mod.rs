@@ -21,9 +21,10 @@ pub use self::queries::{AgentProperties, DelayCause};
 use crate::{
     AgentID, AlertLocation, Analytics, CarID, Command, CreateCar, DrivingSimState, Event,
     IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingSim, ParkingSimState,
-    ParkingSpot, Person, PersonID, Router, Scheduler, SidewalkPOI, SidewalkSpot, StartTripArgs,
-    TrafficRecorder, TransitSimState, TripID, TripInfo, TripManager, TripPhaseType, Vehicle,
-    VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
+    ParkingSpot, Person, PersonID, Router, Scheduler, SidewalkPOI, SidewalkSpot, SpawnFailure,
+    StartTripArgs, TrafficRecorder, TransitSimState, TripID, TripInfo, TripManager, TripPhaseType,
+    Vehicle, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH,
+    MIN_CAR_LENGTH,
 };
 
 mod queries;
@@ -232,17 +233,19 @@ impl Sim {
 
     pub(crate) fn spawn_trips(
         &mut self,
-        input: Vec<(PersonID, TripInfo, StartTripArgs)>,
+        input: Vec<(PersonID, TripInfo, StartTripArgs, Option<SpawnFailure>)>,
         map: &Map,
         timer: &mut Timer,
     ) {
         timer.start_iter("spawn trips", input.len());
-        for (p, info, args) in input {
+        for (p, info, args, failure) in input {
             timer.next();
 
             let trip = self.trips.new_trip(p, info.clone());
-            // This might be immediately true due to ScenarioModifiers
-            if let Some(msg) = info.cancellation_reason {
+            if let Some(error) = failure {
+                self.trips.fail_unstarted_trip(trip, error);
+            } else if let Some(msg) = info.cancellation_reason {
+                // This might be immediately true due to ScenarioModifiers
                 self.trips.cancel_unstarted_trip(trip, msg);
             } else {
                 self.scheduler
@@ -316,9 +319,12 @@ impl Sim {
         &mut self,
         orig_id: Option<OrigPersonID>,
         ped_speed: Speed,
+        wheelchair: bool,
+        household: Option<usize>,
         vehicle_specs: Vec<VehicleSpec>,
     ) -> &Person {
-        self.trips.new_person(orig_id, ped_speed, vehicle_specs)
+        self.trips
+            .new_person(orig_id, ped_speed, wheelchair, household, vehicle_specs)
     }
     pub(crate) fn seed_parked_car(&mut self, vehicle: Vehicle, spot: ParkingSpot) {
         self.parking.reserve_spot(spot, vehicle.id);
@@ -635,6 +641,9 @@ impl Sim {
             Command::StartBus(r, _) => {
                 self.start_bus(map.get_br(r), map);
             }
+            Command::ExpireDelayedTrip(id) => {
+                self.trips.expire_delayed_trip(id);
+            }
         }
 
         // Record events at precisely the time they occur.
@@ -15,8 +15,8 @@ use crate::analytics::SlidingWindow;
 use crate::{
     AgentID, AgentType, Analytics, CarID, CommutersVehiclesCounts, DrawCarInput, DrawPedCrowdInput,
     DrawPedestrianInput, OrigPersonID, PandemicModel, ParkedCar, ParkingSim, PedestrianID, Person,
-    PersonID, PersonState, Scenario, Sim, TripEndpoint, TripID, TripInfo, TripMode, TripResult,
-    UnzoomedAgent, VehicleType,
+    PersonID, PersonState, Scenario, Sim, SpawnFailureKind, TripEndpoint, TripID, TripInfo,
+    TripMode, TripResult, UnzoomedAgent, VehicleType,
 };
 
 // TODO Many of these just delegate to an inner piece. This is unorganized and hard to maintain.
@@ -131,6 +131,10 @@ impl Sim {
     pub fn all_trip_info(&self) -> Vec<(TripID, TripInfo)> {
         self.trips.all_trip_info()
     }
+    /// Categorizes every trip that was cancelled because it couldn't be spawned.
+    pub fn spawn_failures(&self) -> &BTreeMap<TripID, SpawnFailureKind> {
+        self.trips.spawn_failures()
+    }
     /// If trip is finished, returns (total time, total waiting time, total distance)
     pub fn finished_trip_details(&self, id: TripID) -> Option<(Duration, Duration, Distance)> {
         self.trips.finished_trip_details(id)
@@ -12,9 +12,9 @@ use crate::sim::Ctx;
 use crate::{
     AgentID, AgentType, AlertLocation, CarID, Command, CreateCar, CreatePedestrian, DrivingGoal,
     Event, IndividTrip, OrigPersonID, ParkedCar, ParkingSim, ParkingSpot, PedestrianID, PersonID,
-    PersonSpec, Scenario, SidewalkPOI, SidewalkSpot, StartTripArgs, TransitSimState, TripEndpoint,
-    TripID, TripPhaseType, TripPurpose, TripSpec, Vehicle, VehicleSpec, VehicleType,
-    WalkingSimState,
+    PersonSpec, Scenario, SidewalkPOI, SidewalkSpot, SpawnFailure, SpawnFailureKind, StartTripArgs,
+    TransitSimState, TripEndpoint, TripID, TripPhaseType, TripPurpose, TripSpec, Vehicle,
+    VehicleSpec, VehicleType, WalkingSimState,
 };
 
 /// Manages people, each of which executes some trips through the day. Each trip is further broken
@@ -34,6 +34,11 @@ pub(crate) struct TripManager {
     )]
     active_trip_mode: BTreeMap<AgentID, TripID>,
     unfinished_trips: usize,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    spawn_failures: BTreeMap<TripID, SpawnFailureKind>,
 
     car_id_counter: usize,
 
@@ -48,6 +53,7 @@ impl TripManager {
             people: Vec::new(),
             active_trip_mode: BTreeMap::new(),
             unfinished_trips: 0,
+            spawn_failures: BTreeMap::new(),
             car_id_counter: 0,
             events: Vec::new(),
         }
@@ -58,6 +64,8 @@ impl TripManager {
         &mut self,
         orig_id: Option<OrigPersonID>,
         ped_speed: Speed,
+        wheelchair: bool,
+        household: Option<usize>,
         vehicle_specs: Vec<VehicleSpec>,
     ) -> &Person {
         let id = PersonID(self.people.len());
@@ -79,6 +87,8 @@ impl TripManager {
             state: PersonState::OffMap,
             ped: PedestrianID(id.0),
             ped_speed,
+            wheelchair,
+            household,
             vehicles,
             delayed_trips: Vec::new(),
             on_bus: None,
@@ -145,6 +155,12 @@ impl TripManager {
                     ),
                 ));
             }
+            // Don't wait for the previous trip to finish before noticing this one waited too long
+            if let Some(max_delay) = args.max_start_delay {
+                let deadline = self.trips[trip.0].info.departure + max_delay;
+                ctx.scheduler
+                    .push(deadline.max(now), Command::ExpireDelayedTrip(trip));
+            }
             person.delayed_trips.push((trip, args));
             self.events.push(Event::TripPhaseStarting(
                 trip,
@@ -163,12 +179,13 @@ impl TripManager {
             info.mode,
             args.use_vehicle,
             args.retry_if_no_room,
+            person.wheelchair,
             ctx.map,
         ) {
             Ok(spec) => spec,
             Err(error) => TripSpec::SpawningFailure {
                 use_vehicle: args.use_vehicle,
-                error: error.to_string(),
+                error,
             },
         };
         // to_plan might actually change the TripSpec
@@ -225,7 +242,8 @@ impl TripManager {
                 use_vehicle, error, ..
             } => {
                 let vehicle = use_vehicle.map(|v| person.get_vehicle(v));
-                self.cancel_trip(now, trip, error, vehicle, ctx);
+                self.spawn_failures.insert(trip, error.kind);
+                self.cancel_trip(now, trip, error.to_string(), vehicle, ctx);
             }
             TripSpec::UsingParkedCar {
                 car, start_bldg, ..
@@ -277,7 +295,7 @@ impl TripManager {
                     );
                 }
             }
-            TripSpec::JustWalking { start, goal } => {
+            TripSpec::JustWalking { start, goal, .. } => {
                 assert_eq!(
                     person.state,
                     match start.connection {
@@ -914,6 +932,35 @@ impl TripManager {
             return;
         }
         let (trip, args) = person.delayed_trips.remove(0);
+        if self.trips[trip.0].info.cancellation_reason.is_some() {
+            // expire_delayed_trip already gave up on this one. Now that the person is free, warp
+            // them (and maybe their car) along, so the next delayed trip starts from the right
+            // place.
+            let vehicle = args.use_vehicle.map(|v| person.get_vehicle(v));
+            self.warp_after_cancel(now, trip, vehicle, ctx);
+            return;
+        }
+        if let Some(max_delay) = args.max_start_delay {
+            let delay = now - self.trips[trip.0].info.departure;
+            if delay > max_delay {
+                let vehicle = args.use_vehicle.map(|v| person.get_vehicle(v));
+                self.trips[trip.0].started = true;
+                self.spawn_failures
+                    .insert(trip, SpawnFailureKind::WaitedTooLong);
+                // This will try to start the next delayed trip, if there is one
+                self.cancel_trip(
+                    now,
+                    trip,
+                    format!(
+                        "couldn't start within {} of the scheduled departure",
+                        max_delay
+                    ),
+                    vehicle,
+                    ctx,
+                );
+                return;
+            }
+        }
         if false {
             self.events.push(Event::Alert(
                 AlertLocation::Person(person.id),
@@ -968,6 +1015,39 @@ impl TripManager {
             .push(Event::TripCancelled(trip.id, trip.info.mode));
     }
 
+    /// Cancel a trip before it's started, because it couldn't be spawned. The person will stay
+    /// where they are.
+    pub fn fail_unstarted_trip(&mut self, id: TripID, error: SpawnFailure) {
+        self.spawn_failures.insert(id, error.kind);
+        self.cancel_unstarted_trip(id, error.to_string());
+    }
+
+    /// A delayed trip has waited max_start_delay past its departure and its person is still busy
+    /// with an earlier trip. Cancel it right away; the person catches up with it once they're
+    /// free. Does nothing if the trip managed to start in time.
+    pub fn expire_delayed_trip(&mut self, id: TripID) {
+        let trip = &mut self.trips[id.0];
+        if trip.started || trip.info.cancellation_reason.is_some() {
+            return;
+        }
+        trip.started = true;
+        let max_delay = self.people[trip.person.0]
+            .delayed_trips
+            .iter()
+            .find(|(t, _)| *t == id)
+            .and_then(|(_, args)| args.max_start_delay)
+            .unwrap();
+        self.spawn_failures
+            .insert(id, SpawnFailureKind::WaitedTooLong);
+        self.cancel_unstarted_trip(
+            id,
+            format!(
+                "couldn't start within {} of the scheduled departure",
+                max_delay
+            ),
+        );
+    }
+
     /// Cancel a trip after it's started. The person will be magically warped to their destination,
     /// along with their car, as if the trip had completed normally.
     pub fn cancel_trip(
@@ -983,6 +1063,19 @@ impl TripManager {
         trip.info.cancellation_reason = Some(reason);
         self.events
             .push(Event::TripCancelled(trip.id, trip.info.mode));
+        self.warp_after_cancel(now, id, abandoned_vehicle, ctx);
+    }
+
+    /// After a trip is cancelled, warp the person and their car to the trip's destination, then
+    /// move on to their next delayed trip.
+    fn warp_after_cancel(
+        &mut self,
+        now: Time,
+        id: TripID,
+        abandoned_vehicle: Option<Vehicle>,
+        ctx: &mut Ctx,
+    ) {
+        let trip = &self.trips[id.0];
         let person = trip.person;
 
         // Maintain consistentency for anyone listening to events
@@ -1226,6 +1319,10 @@ impl TripManager {
             t.total_distance,
         ))
     }
+    pub fn spawn_failures(&self) -> &BTreeMap<TripID, SpawnFailureKind> {
+        &self.spawn_failures
+    }
+
     pub fn trip_blocked_time(&self, id: TripID) -> Duration {
         let t = &self.trips[id.0];
         t.total_blocked_time
@@ -1283,6 +1380,8 @@ impl TripManager {
         for p in &self.people {
             scenario.people.push(PersonSpec {
                 orig_id: p.orig_id,
+                wheelchair: p.wheelchair,
+                household: p.household,
                 trips: p
                     .trips
                     .iter()
@@ -1456,6 +1555,10 @@ pub struct Person {
 
     pub ped: PedestrianID,
     pub ped_speed: Speed,
+    /// Walking trips should avoid stairs and steep paths.
+    pub wheelchair: bool,
+    /// See `PersonSpec::household`
+    pub household: Option<usize>,
     /// Both cars and bikes
     pub vehicles: Vec<Vehicle>,
 
@@ -13,9 +13,9 @@ use map_model::{IntersectionID, Map};
 use sim::{IndividTrip, PersonSpec, Scenario, TripEndpoint, TripMode, TripPurpose};
 
 fn main() -> Result<()> {
-    test_lane_changing(&import_map(abstio::path(
-        "../tests/input/lane_selection.osm",
-    )))?;
+    let lane_selection = import_map(abstio::path("../tests/input/lane_selection.osm"));
+    test_lane_changing(&lane_selection)?;
+    test_max_start_delay(&lane_selection)?;
     test_map_importer()?;
     check_proposals()?;
     smoke_test()?;
@@ -193,6 +193,8 @@ fn test_lane_changing(map: &Map) -> Result<()> {
     for (idx, (from, to)) in od.into_iter().enumerate() {
         scenario.people.push(PersonSpec {
             orig_id: None,
+            wheelchair: false,
+            household: None,
             trips: vec![IndividTrip::new(
                 // Space out the spawn times a bit. If a vehicle tries to spawn and something's in
                 // the way, there's a fixed retry time in the simulation that we'll hit.
@@ -237,3 +239,74 @@ fn test_lane_changing(map: &Map) -> Result<()> {
 
     Ok(())
 }
+
+/// A person's first trip is still underway when their second trip is scheduled to start. The second
+/// trip has a tiny max_start_delay, so it should be cancelled as soon as that passes, without
+/// waiting for the first trip to finish.
+fn test_max_start_delay(map: &Map) -> Result<()> {
+    let north = IntersectionID(7);
+    let south = IntersectionID(0);
+    let west = IntersectionID(3);
+
+    let first = IndividTrip::new(
+        Time::START_OF_DAY,
+        TripPurpose::Shopping,
+        TripEndpoint::Border(north),
+        TripEndpoint::Border(south),
+        TripMode::Drive,
+    );
+    let mut second = IndividTrip::new(
+        Time::START_OF_DAY + Duration::seconds(1.0),
+        TripPurpose::Shopping,
+        TripEndpoint::Border(south),
+        TripEndpoint::Border(west),
+        TripMode::Drive,
+    );
+    let max_delay = Duration::seconds(1.0);
+    second.max_start_delay = Some(max_delay);
+    let deadline = second.depart + max_delay;
+
+    let mut scenario = Scenario::empty(map, "max_start_delay");
+    scenario.people.push(PersonSpec {
+        orig_id: None,
+        wheelchair: false,
+        household: None,
+        trips: vec![first, second],
+    });
+
+    let mut opts = sim::SimOptions::new("test_max_start_delay");
+    opts.alerts = sim::AlertHandler::Silence;
+    let mut sim = sim::Sim::new(map, opts);
+    let mut rng = sim::SimFlags::for_test("test_max_start_delay").make_rng();
+    scenario.instantiate(&mut sim, map, &mut rng, &mut Timer::throwaway());
+    let trips: Vec<sim::TripID> = sim.all_trip_info().into_iter().map(|(id, _)| id).collect();
+
+    sim.timed_step(
+        map,
+        deadline + Duration::seconds(0.1) - Time::START_OF_DAY,
+        &mut None,
+        &mut Timer::throwaway(),
+    );
+    if sim.finished_trip_details(trips[0]).is_some() {
+        anyhow::bail!(
+            "The first trip finished before {}; it should overrun",
+            deadline
+        );
+    }
+    if sim.spawn_failures().get(&trips[1]) != Some(&sim::SpawnFailureKind::WaitedTooLong) {
+        anyhow::bail!(
+            "The second trip should've been cancelled at {}, but it wasn't by {}",
+            deadline,
+            sim.time()
+        );
+    }
+
+    // The first trip should still finish normally
+    while !sim.is_done() {
+        sim.tiny_step(map, &mut None);
+    }
+    if sim.finished_trip_details(trips[0]).is_none() {
+        anyhow::bail!("The first trip never finished");
+    }
+    Ok(())
+}
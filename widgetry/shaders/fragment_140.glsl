@@ -4,6 +4,8 @@
 uniform vec3 transform;
 // (window width, window height, z value)
 uniform vec3 window;
+// Multiplied into the alpha of every color
+uniform float alpha;
 // textures grid
 uniform sampler2DArray textures;
 
@@ -14,6 +16,7 @@ out vec4 out_color;
 
 void main() {
     vec4 x = fs_color * texture(textures, fs_texture_coord);
+    x.a *= alpha;
     out_color = vec4(x.a * x.r, x.a * x.g, x.a * x.b, x.a);
 
     // Enable this to quickly see everything in greyscale.
@@ -7,6 +7,8 @@ precision mediump sampler2D;
 uniform vec3 transform;
 // (window width, window height, z value)
 uniform vec3 window;
+// Multiplied into the alpha of every color
+uniform float alpha;
 
 // in
 varying vec4 fs_color;
@@ -27,6 +29,7 @@ void main() {
     vec4 tex_color = vec4(1.0, 1.0, 1.0, 1.0);
 
     vec4 x = fs_color * tex_color;
+    x.a *= alpha;
     vec4 out_color = vec4(x.a * x.r, x.a * x.g, x.a * x.b, x.a);
     gl_FragColor = out_color;
 }
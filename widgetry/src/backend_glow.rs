@@ -76,6 +76,7 @@ pub struct GfxCtxInnards<'a> {
     current_clip: Option<[i32; 4]>,
     transform_location: <glow::Context as glow::HasContext>::UniformLocation,
     window_location: <glow::Context as glow::HasContext>::UniformLocation,
+    alpha_location: <glow::Context as glow::HasContext>::UniformLocation,
 }
 
 impl<'a> GfxCtxInnards<'a> {
@@ -83,10 +84,11 @@ impl<'a> GfxCtxInnards<'a> {
         gl: &'a glow::Context,
         program: &'a <glow::Context as glow::HasContext>::Program,
     ) -> Self {
-        let (transform_location, window_location) = unsafe {
+        let (transform_location, window_location, alpha_location) = unsafe {
             (
                 gl.get_uniform_location(*program, "transform").unwrap(),
                 gl.get_uniform_location(*program, "window").unwrap(),
+                gl.get_uniform_location(*program, "alpha").unwrap(),
             )
         };
         GfxCtxInnards {
@@ -94,6 +96,7 @@ impl<'a> GfxCtxInnards<'a> {
             current_clip: None,
             transform_location,
             window_location,
+            alpha_location,
         }
     }
 
@@ -113,6 +116,8 @@ impl<'a> GfxCtxInnards<'a> {
                 .uniform_3_f32_slice(Some(&self.transform_location), &uniforms.transform);
             self.gl
                 .uniform_3_f32_slice(Some(&self.window_location), &uniforms.window);
+            self.gl
+                .uniform_1_f32(Some(&self.alpha_location), uniforms.alpha);
 
             self.gl.bind_vertex_array(Some(obj.vert_array.id));
             self.gl
@@ -188,6 +193,11 @@ impl Drawable {
     pub fn empty(ctx: &EventCtx) -> Drawable {
         ctx.upload(GeomBatch::new())
     }
+
+    /// True if drawing this has no effect.
+    pub fn is_empty(&self) -> bool {
+        self.num_indices == 0
+    }
 }
 
 struct VertexArray {
@@ -25,6 +25,8 @@ pub struct Uniforms {
     pub transform: [f32; 3],
     // (window_width, window_height, Z values)
     pub window: [f32; 3],
+    // Multiplied into the alpha of every color
+    pub alpha: f32,
 }
 
 impl Uniforms {
@@ -40,6 +42,7 @@ impl Uniforms {
                 canvas.window_height as f32,
                 MAPSPACE_Z,
             ],
+            alpha: 1.0,
         }
     }
 }
@@ -139,6 +142,14 @@ impl<'a> GfxCtx<'a> {
         // println!("{:?}", backtrace::Backtrace::new());
     }
 
+    /// Draws with some transparency applied uniformly to everything. This is much cheaper than
+    /// uploading a recolored batch, so it's useful for animating.
+    pub fn redraw_with_alpha(&mut self, obj: &Drawable, alpha: f32) {
+        self.uniforms.alpha = alpha;
+        self.redraw(obj);
+        self.uniforms.alpha = 1.0;
+    }
+
     pub fn redraw_at(&mut self, top_left: ScreenPt, obj: &Drawable) {
         self.fork(Pt2D::new(0.0, 0.0), top_left, 1.0, None);
         self.redraw(obj);
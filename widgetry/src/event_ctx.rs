@@ -152,6 +152,10 @@ impl<'a> EventCtx<'a> {
             .set_cursor_icon(winit::window::CursorIcon::Grabbing);
     }
 
+    pub(crate) fn set_cursor_icon(&mut self, icon: winit::window::CursorIcon) {
+        self.prerender.inner.set_cursor_icon(icon);
+    }
+
     pub fn style(&self) -> &Style {
         self.style
     }
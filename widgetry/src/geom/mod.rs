@@ -234,6 +234,17 @@ impl GeomBatch {
         self
     }
 
+    /// Clips every polygon in the batch to a boundary, dropping anything entirely outside of it.
+    pub fn clip(self, boundary: &Polygon) -> GeomBatch {
+        let mut batch = GeomBatch::new();
+        for (fill, poly, z) in self.list {
+            for piece in poly.intersection(boundary) {
+                batch.list.push((fill.clone(), piece, z));
+            }
+        }
+        batch
+    }
+
     /// Overrides the Z-ordering offset for the batch. Must be in (-1, 0], with values closer to -1
     /// rendering on top.
     pub fn set_z_offset(mut self, offset: f64) -> GeomBatch {
@@ -1,7 +1,9 @@
 mod world;
 
+use geom::Polygon;
+
 use crate::{Drawable, EventCtx, GeomBatch, GfxCtx, RewriteColor};
-pub use world::{DummyID, ObjectID, World, WorldOutcome};
+pub use world::{DummyID, HighlightStyle, HoverCursor, ObjectID, World, WorldOutcome};
 
 /// Draws one of two versions of something, based on whether the canvas is zoomed in past a threshold.
 pub struct ToggleZoomed {
@@ -10,6 +12,8 @@ pub struct ToggleZoomed {
     pub zoomed: Drawable,
     // Draw the same thing whether zoomed or unzoomed
     always_draw_unzoomed: bool,
+    // Draw the zoomed version, even when unzoomed
+    always_draw_zoomed: bool,
 }
 
 impl ToggleZoomed {
@@ -18,6 +22,7 @@ impl ToggleZoomed {
             unzoomed: ctx.upload(unzoomed),
             zoomed: ctx.upload(zoomed),
             always_draw_unzoomed: false,
+            always_draw_zoomed: false,
         }
     }
 
@@ -26,6 +31,7 @@ impl ToggleZoomed {
             unzoomed: Drawable::empty(ctx),
             zoomed: Drawable::empty(ctx),
             always_draw_unzoomed: false,
+            always_draw_zoomed: false,
         }
     }
 
@@ -34,14 +40,33 @@ impl ToggleZoomed {
             unzoomed: GeomBatch::new(),
             zoomed: GeomBatch::new(),
             always_draw_unzoomed: false,
+            always_draw_zoomed: false,
         }
     }
 
+    /// True if neither version draws anything.
+    pub fn is_empty(&self) -> bool {
+        self.unzoomed.is_empty() && self.zoomed.is_empty()
+    }
+
     pub fn draw(&self, g: &mut GfxCtx) {
-        if self.always_draw_unzoomed || g.canvas.cam_zoom < g.canvas.settings.min_zoom_for_detail {
-            g.redraw(&self.unzoomed);
+        g.redraw(self.pick(g));
+    }
+
+    /// Draws with some transparency applied uniformly to whichever version is used. Useful for
+    /// fading in and out without rebuilding.
+    pub fn draw_with_alpha(&self, g: &mut GfxCtx, alpha: f32) {
+        g.redraw_with_alpha(self.pick(g), alpha);
+    }
+
+    fn pick(&self, g: &GfxCtx) -> &Drawable {
+        if self.always_draw_unzoomed
+            || (!self.always_draw_zoomed
+                && g.canvas.cam_zoom < g.canvas.settings.min_zoom_for_detail)
+        {
+            &self.unzoomed
         } else {
-            g.redraw(&self.zoomed);
+            &self.zoomed
         }
     }
 }
@@ -51,9 +76,17 @@ pub struct ToggleZoomedBuilder {
     pub unzoomed: GeomBatch,
     pub zoomed: GeomBatch,
     always_draw_unzoomed: bool,
+    always_draw_zoomed: bool,
 }
 
 impl ToggleZoomedBuilder {
+    /// Always draw the zoomed version, no matter how far the canvas is zoomed out. If this was
+    /// built from a single batch, nothing changes; it's already drawn the same way at all zooms.
+    pub fn always_zoomed(mut self) -> Self {
+        self.always_draw_zoomed = true;
+        self
+    }
+
     /// Transforms all colors in both batches.
     pub fn color(mut self, transformation: RewriteColor) -> Self {
         self.unzoomed = self.unzoomed.color(transformation);
@@ -61,6 +94,20 @@ impl ToggleZoomedBuilder {
         self
     }
 
+    /// Translates both batches by some offset.
+    pub fn translate(mut self, dx: f64, dy: f64) -> Self {
+        self.unzoomed = self.unzoomed.translate(dx, dy);
+        self.zoomed = self.zoomed.translate(dx, dy);
+        self
+    }
+
+    /// Clips both batches to a boundary, so nothing outside of it is uploaded or drawn.
+    pub fn clip(mut self, boundary: &Polygon) -> Self {
+        self.unzoomed = self.unzoomed.clip(boundary);
+        self.zoomed = self.zoomed.clip(boundary);
+        self
+    }
+
     pub fn build(self, ctx: &EventCtx) -> ToggleZoomed {
         if self.always_draw_unzoomed {
             assert!(self.zoomed.is_empty());
@@ -69,6 +116,20 @@ impl ToggleZoomedBuilder {
             unzoomed: ctx.upload(self.unzoomed),
             zoomed: ctx.upload(self.zoomed),
             always_draw_unzoomed: self.always_draw_unzoomed,
+            always_draw_zoomed: self.always_draw_zoomed,
+        }
+    }
+
+    /// Like `build`, but for things first uploaded while drawing.
+    pub(crate) fn build_while_drawing(self, g: &mut GfxCtx) -> ToggleZoomed {
+        if self.always_draw_unzoomed {
+            assert!(self.zoomed.is_empty());
+        }
+        ToggleZoomed {
+            unzoomed: g.upload(self.unzoomed),
+            zoomed: g.upload(self.zoomed),
+            always_draw_unzoomed: self.always_draw_unzoomed,
+            always_draw_zoomed: self.always_draw_zoomed,
         }
     }
 }
@@ -80,6 +141,7 @@ impl std::convert::From<GeomBatch> for ToggleZoomedBuilder {
             unzoomed,
             zoomed: GeomBatch::new(),
             always_draw_unzoomed: true,
+            always_draw_zoomed: false,
         }
     }
 }
@@ -1,13 +1,19 @@
-use std::collections::HashMap;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::hash::Hash;
 
 use aabb_quadtree::{ItemId, QuadTree};
+use instant::Instant;
 
-use geom::{Bounds, Circle, Distance, Polygon, Pt2D};
+use geom::{Bounds, Circle, Distance, Duration, PolyLine, Polygon, Pt2D};
 
+use crate::drawing::MAPSPACE_Z;
 use crate::mapspace::{ToggleZoomed, ToggleZoomedBuilder};
-use crate::{Color, EventCtx, GeomBatch, GfxCtx, MultiKey, RewriteColor, Text};
+use crate::{
+    Color, EventCtx, GeomBatch, GfxCtx, Key, MultiKey, RewriteColor, ScreenPt, Text, UpdateType,
+};
 
 // TODO Tests...
 // - start drag in screenspace, release in map
@@ -23,11 +29,55 @@ pub struct World<ID: ObjectID> {
     quadtree: QuadTree<ID>,
 
     draw_master_batches: Vec<ToggleZoomed>,
+    overlay_draws: Vec<Box<dyn Fn(&mut GfxCtx)>>,
+    hidden_categories: HashSet<String>,
+    highlight_style: Option<HighlightStyle>,
 
     hovering: Option<ID>,
-    // If we're currently dragging, where was the cursor during the last movement, and has the
-    // cursor moved since starting the drag?
-    dragging_from: Option<(Pt2D, bool)>,
+    // In screen-space pixels. Objects can override this.
+    hitbox_inflation: f64,
+    // The largest inflation of any object, to know how far to search the quadtree
+    max_hitbox_inflation: f64,
+    dragging_from: Option<DragState>,
+    // In screen-space pixels, how far the cursor has to move before a drag starts. Objects can
+    // override this.
+    drag_threshold: f64,
+    // If set, draw a translucent copy of the dragged object at the cursor, with this alpha
+    drag_ghost_alpha: Option<f32>,
+    // Where the cursor was in map-space during the last event
+    cursor: Option<Pt2D>,
+    show_crosshair: bool,
+    following: Option<ID>,
+    declutter_labels: bool,
+    interactive: bool,
+    // An object to draw attention to, and when the pulsing started
+    pulsing: Option<(ID, Instant)>,
+    // How many objects have a dashed outline that moves, and when the animation started
+    num_animated: usize,
+    animation_start: Instant,
+    highlighted: HashSet<ID>,
+    prefer_smaller_hitboxes: bool,
+    pan_bounds: Option<Bounds>,
+    coalesce_outcomes: bool,
+    highlighted_style: HighlightStyle,
+    // How many objects have been added, to break ties in zorder by registration order
+    num_added: usize,
+    // Apply to every hovered object, after the object's own keybindings
+    keybindings: Vec<(MultiKey, &'static str)>,
+    scroll_modifier: Option<Key>,
+    hover_fade: Option<Duration>,
+    // The object most recently hovered on, and when the cursor left it
+    fading: Option<(ID, Instant)>,
+    active_tool: Option<&'static str>,
+}
+
+/// If we're currently dragging, where was the cursor when the drag started and during the last
+/// movement, and has the cursor moved since starting the drag?
+#[derive(Clone, Copy)]
+struct DragState {
+    start: Pt2D,
+    last: Pt2D,
+    moved: bool,
 }
 
 /// The result of a `World` handling an event
@@ -42,10 +92,45 @@ pub enum WorldOutcome<ID: ObjectID> {
         dy: f64,
         cursor: Pt2D,
     },
+    /// The user released the mouse after dragging an object. The given offsets are relative to
+    /// where the drag started. This is only fired if the object actually moved.
+    DragEnd { obj: ID, dx: f64, dy: f64 },
     /// While hovering on an object with a defined hotkey, that key was pressed.
     Keypress(&'static str, ID),
     /// A hoverable object was clicked
     ClickedObject(ID),
+    /// While hovering on an object and holding the key from `World::set_scroll_modifier`, the
+    /// mouse wheel scrolled vertically by this amount. The canvas doesn't zoom in this case.
+    Scrolled(ID, f64),
+    /// While a tool from `World::set_active_tool` is active, the user clicked. This replaces
+    /// `ClickedObject` and `ClickedFreeSpace`. The object hovered on, if any, and the map-space
+    /// position of the click are included.
+    ToolAction {
+        tool: &'static str,
+        obj: Option<ID>,
+        pt: Pt2D,
+    },
+    /// While a tool from `World::set_active_tool` is active, the user is dragging. This replaces
+    /// `Dragging`. The object where the drag started, if any, is included. The given offsets are
+    /// relative to the previous dragging event.
+    ToolDragging {
+        tool: &'static str,
+        obj: Option<ID>,
+        dx: f64,
+        dy: f64,
+        cursor: Pt2D,
+    },
+    /// While a tool from `World::set_active_tool` is active, the user released the mouse after
+    /// dragging. This replaces `DragEnd`. The given offsets are relative to where the drag
+    /// started.
+    ToolDragEnd {
+        tool: &'static str,
+        obj: Option<ID>,
+        dx: f64,
+        dy: f64,
+    },
+    /// The cursor started or stopped hovering on an object, or moved directly from one to another
+    HoverChanged { from: Option<ID>, to: Option<ID> },
     /// Nothing interesting happened
     Nothing,
 }
@@ -68,13 +153,156 @@ impl<I: ObjectID> WorldOutcome<I> {
                 dy,
                 cursor,
             },
+            WorldOutcome::DragEnd { obj, dx, dy } => WorldOutcome::DragEnd {
+                obj: f(obj),
+                dx,
+                dy,
+            },
             WorldOutcome::Keypress(action, id) => WorldOutcome::Keypress(action, f(id)),
             WorldOutcome::ClickedObject(id) => WorldOutcome::ClickedObject(f(id)),
+            WorldOutcome::Scrolled(id, dy) => WorldOutcome::Scrolled(f(id), dy),
+            WorldOutcome::ToolAction { tool, obj, pt } => WorldOutcome::ToolAction {
+                tool,
+                obj: obj.map(&f),
+                pt,
+            },
+            WorldOutcome::ToolDragging {
+                tool,
+                obj,
+                dx,
+                dy,
+                cursor,
+            } => WorldOutcome::ToolDragging {
+                tool,
+                obj: obj.map(&f),
+                dx,
+                dy,
+                cursor,
+            },
+            WorldOutcome::ToolDragEnd { tool, obj, dx, dy } => WorldOutcome::ToolDragEnd {
+                tool,
+                obj: obj.map(&f),
+                dx,
+                dy,
+            },
+            WorldOutcome::HoverChanged { from, to } => WorldOutcome::HoverChanged {
+                from: from.map(&f),
+                to: to.map(&f),
+            },
             WorldOutcome::Nothing => WorldOutcome::Nothing,
         }
     }
 }
 
+/// An extra highlight drawn on top of an object, based on its hitbox. This is useful when the
+/// object's normal hovered drawing would obscure important detail underneath.
+#[derive(Clone, Debug)]
+pub struct HighlightStyle {
+    /// Fill the hitbox with this color
+    pub fill: Option<Color>,
+    /// Outline the hitbox with this color and thickness
+    pub outline: Option<(Color, Distance)>,
+}
+
+impl HighlightStyle {
+    pub fn fill(color: Color) -> HighlightStyle {
+        HighlightStyle {
+            fill: Some(color),
+            outline: None,
+        }
+    }
+
+    pub fn outline(color: Color, thickness: Distance) -> HighlightStyle {
+        HighlightStyle {
+            fill: None,
+            outline: Some((color, thickness)),
+        }
+    }
+
+    pub fn fill_and_outline(fill: Color, outline: Color, thickness: Distance) -> HighlightStyle {
+        HighlightStyle {
+            fill: Some(fill),
+            outline: Some((outline, thickness)),
+        }
+    }
+
+    fn render(&self, hitbox: &Polygon) -> GeomBatch {
+        let mut batch = GeomBatch::new();
+        if let Some(color) = self.fill {
+            batch.push(color, hitbox.clone());
+        }
+        if let Some((color, thickness)) = self.outline {
+            // Not every polygon can produce an outline
+            if let Ok(outline) = hitbox.to_outline(thickness) {
+                batch.push(color, outline);
+            }
+        }
+        batch
+    }
+}
+
+#[derive(Clone, Copy)]
+struct DashedOutline {
+    color: Color,
+    thickness: Distance,
+    dash_len: Distance,
+    // Meters per second
+    speed: f64,
+}
+
+impl DashedOutline {
+    fn render(&self, hitbox: &Polygon, elapsed_seconds: f64) -> GeomBatch {
+        let mut batch = GeomBatch::new();
+        let ring = match hitbox.get_outer_ring() {
+            Some(ring) => ring,
+            None => {
+                return batch;
+            }
+        };
+        let pl = PolyLine::unchecked_new(ring.into_points());
+        let perimeter = pl.length();
+        // Dashes and gaps are the same length
+        let period = self.dash_len * 2.0;
+        // Start one period early, so the dashes wrap around the start of the ring
+        let mut start =
+            Distance::meters((self.speed * elapsed_seconds).rem_euclid(period.inner_meters()))
+                - period;
+        while start < perimeter {
+            let from = start.max(Distance::ZERO);
+            let to = (start + self.dash_len).min(perimeter);
+            if to > from {
+                if let Ok(slice) = pl.maybe_exact_slice(from, to) {
+                    batch.push(self.color, slice.make_polygons(self.thickness));
+                }
+            }
+            start += period;
+        }
+        batch
+    }
+}
+
+/// The mouse cursor to show while hovering on an object
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HoverCursor {
+    /// A pointing hand, for something clickable
+    Pointer,
+    /// Arrows in every direction, for something draggable
+    Move,
+    Crosshair,
+    Text,
+}
+
+impl HoverCursor {
+    fn icon(self) -> winit::window::CursorIcon {
+        match self {
+            HoverCursor::Pointer => winit::window::CursorIcon::Hand,
+            HoverCursor::Move => winit::window::CursorIcon::Move,
+            HoverCursor::Crosshair => winit::window::CursorIcon::Crosshair,
+            HoverCursor::Text => winit::window::CursorIcon::Text,
+        }
+    }
+}
+
 /// Objects in a `World` are uniquely identified by this caller-specified type
 pub trait ObjectID: Clone + Copy + Debug + Eq + Hash {}
 
@@ -83,14 +311,28 @@ pub struct ObjectBuilder<'a, ID: ObjectID> {
     world: &'a mut World<ID>,
 
     id: ID,
+    // When this object was added, relative to others
+    order: usize,
     hitbox: Option<Polygon>,
+    hitbox_inflation: Option<f64>,
     zorder: usize,
+    category: Option<String>,
     draw_normal: Option<ToggleZoomedBuilder>,
+    draw_lazily: Option<Box<dyn Fn() -> ToggleZoomedBuilder>>,
     draw_hover: Option<ToggleZoomedBuilder>,
+    highlight_style: Option<HighlightStyle>,
+    dashed_outline: Option<DashedOutline>,
+    cursor: Option<HoverCursor>,
     tooltip: Option<Text>,
+    label: Option<Text>,
     clickable: bool,
     draggable: bool,
+    drag_threshold: Option<f64>,
     keybindings: Vec<(MultiKey, &'static str)>,
+    data: Option<Box<dyn Any>>,
+    translation: Option<(f64, f64)>,
+    always_detailed: bool,
+    hit_testable: bool,
 }
 
 impl<'a, ID: ObjectID> ObjectBuilder<'a, ID> {
@@ -101,6 +343,18 @@ impl<'a, ID: ObjectID> ObjectBuilder<'a, ID> {
         self
     }
 
+    /// Let the cursor hover on this object when it's within some screen-space pixels of the
+    /// hitbox. This makes thin objects easier to hover on, without changing how they're drawn.
+    /// Overrides `World::set_hitbox_inflation`.
+    pub fn hitbox_inflation(mut self, pixels: f64) -> Self {
+        assert!(
+            self.hitbox_inflation.is_none(),
+            "called hitbox_inflation twice"
+        );
+        self.hitbox_inflation = Some(pixels);
+        self
+    }
+
     /// Provides ordering for overlapping objects. Higher values are "on top" of lower values.
     pub fn zorder(mut self, zorder: usize) -> Self {
         assert!(self.zorder == 0, "called zorder twice");
@@ -108,16 +362,49 @@ impl<'a, ID: ObjectID> ObjectBuilder<'a, ID> {
         self
     }
 
-    /// Specifies how to draw this object normally (while not hovering on it)
+    /// Groups this object into a named category, so that all objects in the category can be hidden
+    /// at once with `World::set_category_visible`.
+    pub fn category<S: Into<String>>(mut self, name: S) -> Self {
+        assert!(self.category.is_none(), "called category twice");
+        self.category = Some(name.into());
+        self
+    }
+
+    /// Specifies how to draw this object normally (while not hovering on it). Passing in a
+    /// `ToggleZoomedBuilder` lets the object look different when zoomed in; a plain `GeomBatch`
+    /// looks the same at all zoom levels.
     pub fn draw<I: Into<ToggleZoomedBuilder>>(mut self, normal: I) -> Self {
         assert!(
-            self.draw_normal.is_none(),
+            self.draw_normal.is_none() && self.draw_lazily.is_none(),
             "already specified how to draw normally"
         );
         self.draw_normal = Some(normal.into());
         self
     }
 
+    /// Like `draw`, but the drawing is only produced and uploaded the first time the object is
+    /// drawn on screen, then cached. This saves time building large worlds when most objects are
+    /// never seen. Since the normal drawing doesn't exist up front, `draw_hover_rewrite` and
+    /// `hover_alpha` can't be used; specify `draw_hovered` directly instead.
+    pub fn draw_lazily<F: 'static + Fn() -> ToggleZoomedBuilder>(mut self, make: F) -> Self {
+        assert!(
+            self.draw_normal.is_none() && self.draw_lazily.is_none(),
+            "already specified how to draw normally"
+        );
+        self.draw_lazily = Some(Box::new(make));
+        self
+    }
+
+    /// Draw the object one way when unzoomed and another when zoomed in, using the same threshold
+    /// as `ToggleZoomed`. Either batch can be empty, so things like labels can appear only when
+    /// zoomed in.
+    pub fn draw_zoomed(self, unzoomed: GeomBatch, zoomed: GeomBatch) -> Self {
+        let mut draw = ToggleZoomed::builder();
+        draw.unzoomed = unzoomed;
+        draw.zoomed = zoomed;
+        self.draw(draw)
+    }
+
     /// Draw the object by coloring its hitbox
     pub fn draw_color(self, color: Color) -> Self {
         let hitbox = self.hitbox.clone().expect("call hitbox first");
@@ -159,6 +446,48 @@ impl<'a, ID: ObjectID> ObjectBuilder<'a, ID> {
         self.draw_hover_rewrite(RewriteColor::ChangeAlpha(alpha))
     }
 
+    /// While hovering on this object, draw an extra highlight on top of it. Overrides
+    /// `World::set_highlight_style`.
+    pub fn highlight_style(mut self, style: HighlightStyle) -> Self {
+        assert!(
+            self.highlight_style.is_none(),
+            "already specified highlight_style"
+        );
+        self.highlight_style = Some(style);
+        self
+    }
+
+    /// Always draw a dashed outline around this object's hitbox, such as for something under
+    /// construction. The dashes crawl around the outline at `speed` meters per second; 0 keeps
+    /// them still.
+    pub fn dashed_outline(
+        mut self,
+        color: Color,
+        thickness: Distance,
+        dash_len: Distance,
+        speed: f64,
+    ) -> Self {
+        assert!(
+            self.dashed_outline.is_none(),
+            "already specified dashed_outline"
+        );
+        assert!(dash_len > Distance::ZERO, "dash_len must be positive");
+        self.dashed_outline = Some(DashedOutline {
+            color,
+            thickness,
+            dash_len,
+            speed,
+        });
+        self
+    }
+
+    /// Change the mouse cursor while hovering on or dragging this object.
+    pub fn cursor(mut self, cursor: HoverCursor) -> Self {
+        assert!(self.cursor.is_none(), "already specified cursor");
+        self.cursor = Some(cursor);
+        self
+    }
+
     /// Draw a tooltip while hovering over this object.
     pub fn tooltip(mut self, txt: Text) -> Self {
         assert!(self.tooltip.is_none(), "already specified tooltip");
@@ -172,6 +501,14 @@ impl<'a, ID: ObjectID> ObjectBuilder<'a, ID> {
         self
     }
 
+    /// Draw some text centered on this object. The label stays the same size on the screen, and
+    /// is only shown when zoomed in. See also `World::set_declutter_labels`.
+    pub fn label(mut self, txt: Text) -> Self {
+        assert!(self.label.is_none(), "already specified label");
+        self.label = Some(txt);
+        self
+    }
+
     /// Mark the object as clickable. `WorldOutcome::ClickedObject` will be fired.
     pub fn clickable(mut self) -> Self {
         assert!(!self.clickable, "called clickable twice");
@@ -190,6 +527,15 @@ impl<'a, ID: ObjectID> ObjectBuilder<'a, ID> {
         self
     }
 
+    /// Require the cursor to move some screen-space pixels before dragging this object starts,
+    /// overriding `World::set_drag_threshold`. Small handles can use 0 to drag immediately, while
+    /// large objects might want more, so that clicking them isn't mistaken for a drag.
+    pub fn drag_threshold(mut self, pixels: f64) -> Self {
+        assert!(self.draggable, "first mark the object draggable");
+        self.drag_threshold = Some(pixels);
+        self
+    }
+
     /// While the user hovers over this object, they can press a key to perform the specified
     /// action. `WorldOutcome::Keypress` will be fired.
     pub fn hotkey<I: Into<MultiKey>>(mut self, key: I, action: &'static str) -> Self {
@@ -198,31 +544,124 @@ impl<'a, ID: ObjectID> ObjectBuilder<'a, ID> {
         self
     }
 
+    /// Attach some arbitrary data to this object, retrievable later with `World::get_data`.
+    pub fn data<T: 'static>(mut self, data: T) -> Self {
+        assert!(self.data.is_none(), "already specified data");
+        self.data = Some(Box::new(data));
+        self
+    }
+
+    /// Move the object by some map-space offset. The hitbox and all drawing are moved together,
+    /// so hovering stays accurate. This is applied when the object is built, so it can be
+    /// specified in any order.
+    pub fn translate(mut self, dx: f64, dy: f64) -> Self {
+        assert!(self.translation.is_none(), "called translate twice");
+        self.translation = Some((dx, dy));
+        self
+    }
+
+    /// Always draw this object's zoomed-in geometry, even when the canvas is zoomed out. This is
+    /// useful for important markers, like a destination pin. Only matters for objects drawn
+    /// differently when zoomed; see `draw_zoomed`.
+    pub fn always_detailed(mut self) -> Self {
+        self.always_detailed = true;
+        self
+    }
+
+    /// By default, the cursor can hover on an object wherever its hitbox is, even if it's drawn
+    /// transparently. If false, the object is purely decorative: it never blocks hovering on or
+    /// clicking objects underneath, no matter how it's drawn.
+    pub fn hit_testable(mut self, hit_testable: bool) -> Self {
+        self.hit_testable = hit_testable;
+        self
+    }
+
+    fn apply_translation(&mut self) {
+        if let Some((dx, dy)) = self.translation.take() {
+            self.hitbox = self.hitbox.take().map(|p| p.translate(dx, dy));
+            self.draw_normal = self.draw_normal.take().map(|d| d.translate(dx, dy));
+            if let Some(make) = self.draw_lazily.take() {
+                self.draw_lazily = Some(Box::new(move || make().translate(dx, dy)));
+            }
+            self.draw_hover = self.draw_hover.take().map(|d| d.translate(dx, dy));
+        }
+    }
+
     /// Finalize the object, adding it to the `World`.
     pub fn build(mut self, ctx: &mut EventCtx) {
+        self.apply_translation();
+        if self.always_detailed {
+            self.draw_normal = self.draw_normal.take().map(|d| d.always_zoomed());
+            self.draw_hover = self.draw_hover.take().map(|d| d.always_zoomed());
+            if let Some(make) = self.draw_lazily.take() {
+                self.draw_lazily = Some(Box::new(move || make().always_zoomed()));
+            }
+        }
         let hitbox = self.hitbox.take().expect("didn't specify hitbox");
+        let draw_normal = match self.draw_lazily.take() {
+            Some(make) => NormalDrawing::Lazy {
+                make,
+                cached: RefCell::new(None),
+            },
+            None => NormalDrawing::Ready(
+                self.draw_normal
+                    .take()
+                    .expect("didn't specify how to draw normally")
+                    .build(ctx),
+            ),
+        };
+        let draw_hover = self.draw_hover.take().map(|draw| draw.build(ctx));
+        let hoverable = draw_hover.is_some();
+        let label = self.label.take().map(|txt| txt.render_autocropped(ctx));
+        self.insert_object(hitbox, draw_normal, draw_hover, hoverable, label);
+    }
+
+    /// The part of `build` that doesn't need to upload anything to the GPU, so tests can add
+    /// objects without a window. `hoverable` is normally whether there's a `draw_hover`.
+    fn insert_object(
+        mut self,
+        hitbox: Polygon,
+        draw_normal: NormalDrawing,
+        draw_hover: Option<ToggleZoomed>,
+        hoverable: bool,
+        label: Option<GeomBatch>,
+    ) {
         let bounds = hitbox.get_bounds();
         let quadtree_id = self
             .world
             .quadtree
             .insert_with_box(self.id, bounds.as_bbox());
+        if let Some(pixels) = self.hitbox_inflation {
+            self.world.max_hitbox_inflation = self.world.max_hitbox_inflation.max(pixels);
+        }
+        if matches!(self.dashed_outline, Some(dashed) if dashed.speed != 0.0) {
+            self.world.num_animated += 1;
+        }
 
         self.world.objects.insert(
             self.id,
             Object {
                 _id: self.id,
-                _quadtree_id: quadtree_id,
+                quadtree_id,
                 hitbox,
+                hitbox_inflation: self.hitbox_inflation,
                 zorder: self.zorder,
-                draw_normal: self
-                    .draw_normal
-                    .expect("didn't specify how to draw normally")
-                    .build(ctx),
-                draw_hover: self.draw_hover.take().map(|draw| draw.build(ctx)),
+                order: self.order,
+                category: self.category,
+                draw_normal,
+                hoverable,
+                draw_hover,
+                highlight_style: self.highlight_style,
+                dashed_outline: self.dashed_outline,
+                cursor: self.cursor,
                 tooltip: self.tooltip,
+                label,
                 clickable: self.clickable,
                 draggable: self.draggable,
+                drag_threshold: self.drag_threshold,
                 keybindings: self.keybindings,
+                data: self.data,
+                hit_testable: self.hit_testable,
             },
         );
     }
@@ -230,17 +669,55 @@ impl<'a, ID: ObjectID> ObjectBuilder<'a, ID> {
 
 struct Object<ID: ObjectID> {
     _id: ID,
-    _quadtree_id: ItemId,
+    quadtree_id: ItemId,
     hitbox: Polygon,
+    hitbox_inflation: Option<f64>,
     zorder: usize,
-    draw_normal: ToggleZoomed,
+    order: usize,
+    category: Option<String>,
+    draw_normal: NormalDrawing,
     draw_hover: Option<ToggleZoomed>,
+    // Only objects with a hovered drawing can be hovered on. This is tracked separately so hit
+    // testing doesn't depend on anything uploaded to the GPU.
+    hoverable: bool,
+    highlight_style: Option<HighlightStyle>,
+    dashed_outline: Option<DashedOutline>,
+    cursor: Option<HoverCursor>,
     tooltip: Option<Text>,
+    // Rendered in screen-space pixels
+    label: Option<GeomBatch>,
     clickable: bool,
     draggable: bool,
+    drag_threshold: Option<f64>,
     // TODO How should we communicate these keypresses are possible? Something standard, like
     // button tooltips?
     keybindings: Vec<(MultiKey, &'static str)>,
+    data: Option<Box<dyn Any>>,
+    hit_testable: bool,
+}
+
+enum NormalDrawing {
+    Ready(ToggleZoomed),
+    // Built and uploaded the first time the object is drawn
+    Lazy {
+        make: Box<dyn Fn() -> ToggleZoomedBuilder>,
+        cached: RefCell<Option<ToggleZoomed>>,
+    },
+}
+
+impl NormalDrawing {
+    fn draw(&self, g: &mut GfxCtx, alpha: f32) {
+        match self {
+            NormalDrawing::Ready(draw) => draw.draw_with_alpha(g, alpha),
+            NormalDrawing::Lazy { make, cached } => {
+                let mut cached = cached.borrow_mut();
+                if cached.is_none() {
+                    *cached = Some(make().build_while_drawing(g));
+                }
+                cached.as_ref().unwrap().draw_with_alpha(g, alpha);
+            }
+        }
+    }
 }
 
 impl<ID: ObjectID> World<ID> {
@@ -254,9 +731,35 @@ impl<ID: ObjectID> World<ID> {
             ),
 
             draw_master_batches: Vec::new(),
+            overlay_draws: Vec::new(),
+            hidden_categories: HashSet::new(),
+            highlight_style: None,
 
             hovering: None,
+            hitbox_inflation: 0.0,
+            max_hitbox_inflation: 0.0,
             dragging_from: None,
+            drag_threshold: 0.0,
+            drag_ghost_alpha: None,
+            cursor: None,
+            show_crosshair: false,
+            following: None,
+            declutter_labels: false,
+            interactive: true,
+            pulsing: None,
+            num_animated: 0,
+            animation_start: Instant::now(),
+            highlighted: HashSet::new(),
+            prefer_smaller_hitboxes: false,
+            pan_bounds: None,
+            coalesce_outcomes: false,
+            highlighted_style: HighlightStyle::fill(Color::CYAN.alpha(0.5)),
+            num_added: 0,
+            keybindings: Vec::new(),
+            scroll_modifier: None,
+            hover_fade: None,
+            fading: None,
+            active_tool: None,
         }
     }
 
@@ -267,9 +770,35 @@ impl<ID: ObjectID> World<ID> {
             quadtree: QuadTree::default(bounds.as_bbox()),
 
             draw_master_batches: Vec::new(),
+            overlay_draws: Vec::new(),
+            hidden_categories: HashSet::new(),
+            highlight_style: None,
 
             hovering: None,
+            hitbox_inflation: 0.0,
+            max_hitbox_inflation: 0.0,
             dragging_from: None,
+            drag_threshold: 0.0,
+            drag_ghost_alpha: None,
+            cursor: None,
+            show_crosshair: false,
+            following: None,
+            declutter_labels: false,
+            interactive: true,
+            pulsing: None,
+            num_animated: 0,
+            animation_start: Instant::now(),
+            highlighted: HashSet::new(),
+            prefer_smaller_hitboxes: false,
+            pan_bounds: None,
+            coalesce_outcomes: false,
+            highlighted_style: HighlightStyle::fill(Color::CYAN.alpha(0.5)),
+            num_added: 0,
+            keybindings: Vec::new(),
+            scroll_modifier: None,
+            hover_fade: None,
+            fading: None,
+            active_tool: None,
         }
     }
 
@@ -277,19 +806,233 @@ impl<ID: ObjectID> World<ID> {
     /// `ObjectBuilder`, then call `build`.
     pub fn add<'a>(&'a mut self, id: ID) -> ObjectBuilder<'a, ID> {
         assert!(!self.objects.contains_key(&id), "duplicate object added");
+        let order = self.num_added;
+        self.num_added += 1;
         ObjectBuilder {
             world: self,
 
             id,
+            order,
             hitbox: None,
+            hitbox_inflation: None,
             zorder: 0,
+            category: None,
             draw_normal: None,
+            draw_lazily: None,
             draw_hover: None,
+            highlight_style: None,
+            dashed_outline: None,
+            cursor: None,
             tooltip: None,
+            label: None,
             clickable: false,
             draggable: false,
+            drag_threshold: None,
             keybindings: Vec::new(),
+            data: None,
+            translation: None,
+            always_detailed: false,
+            hit_testable: true,
+        }
+    }
+
+    /// Attach some arbitrary data to an object, replacing anything previously attached. This saves
+    /// callers from maintaining a parallel map from the ID to their own data.
+    pub fn set_data<T: 'static>(&mut self, id: ID, data: T) {
+        self.objects
+            .get_mut(&id)
+            .expect("set_data on an unknown object")
+            .data = Some(Box::new(data));
+    }
+
+    /// Returns the data attached to an object, if it exists and has the requested type.
+    pub fn get_data<T: 'static>(&self, id: ID) -> Option<&T> {
+        self.objects.get(&id)?.data.as_ref()?.downcast_ref()
+    }
+
+    /// Let the cursor hover on objects when it's within some screen-space pixels of their hitbox.
+    /// This makes thin objects like lanes easier to hover on, without changing how they're drawn.
+    /// Individual objects can override this with `ObjectBuilder::hitbox_inflation`.
+    pub fn set_hitbox_inflation(&mut self, pixels: f64) {
+        self.hitbox_inflation = pixels;
+        self.max_hitbox_inflation = self.max_hitbox_inflation.max(pixels);
+    }
+
+    /// Show or hide all objects in a category. Hidden objects aren't drawn and can't be hovered on.
+    pub fn set_category_visible(&mut self, name: &str, visible: bool) {
+        if visible {
+            self.hidden_categories.remove(name);
+            return;
+        }
+        self.hidden_categories.insert(name.to_string());
+        if let Some(id) = self.hovering {
+            if !self.is_visible(&self.objects[&id]) {
+                self.hovering = None;
+                self.dragging_from = None;
+            }
+        }
+    }
+
+    fn is_visible(&self, obj: &Object<ID>) -> bool {
+        match obj.category {
+            Some(ref category) => !self.hidden_categories.contains(category),
+            None => true,
+        }
+    }
+
+    /// While hovering on any object, draw an extra highlight on top of it. Individual objects can
+    /// override this with `ObjectBuilder::highlight_style`.
+    pub fn set_highlight_style(&mut self, style: Option<HighlightStyle>) {
+        self.highlight_style = style;
+    }
+
+    /// Require the cursor to move some screen-space pixels before dragging an object starts. Until
+    /// then, releasing the mouse counts as a click. Individual objects can override this with
+    /// `ObjectBuilder::drag_threshold`.
+    pub fn set_drag_threshold(&mut self, pixels: f64) {
+        self.drag_threshold = pixels;
+    }
+
+    /// While dragging an object, draw a translucent "ghost" copy of it following the cursor, and
+    /// keep drawing the original in place until the mouse is released. This is meant for callers
+    /// that only move the object when `WorldOutcome::DragEnd` happens. `alpha` is the ghost's
+    /// opacity; pass `None` to stop.
+    pub fn set_drag_ghost(&mut self, alpha: Option<f32>) {
+        self.drag_ghost_alpha = alpha;
+    }
+
+    /// While the user hovers over any object, they can press a key to perform the specified
+    /// action. `WorldOutcome::Keypress` will be fired. This is useful for actions that apply to
+    /// every object, like deleting. If an object has its own `ObjectBuilder::hotkey` for the same
+    /// key, that takes precedence.
+    pub fn hotkey<I: Into<MultiKey>>(&mut self, key: I, action: &'static str) {
+        self.keybindings.push((key.into(), action));
+    }
+
+    /// While hovering on an object and holding this key, scrolling the mouse wheel fires
+    /// `WorldOutcome::Scrolled` instead of zooming. This is useful for adjusting some value tied
+    /// to the object, like scrubbing along a timeline. Note that with the `touchpad_to_move`
+    /// setting, holding `LeftControl` normally zooms. Pass `None` to always zoom.
+    pub fn set_scroll_modifier(&mut self, key: Option<Key>) {
+        self.scroll_modifier = key;
+    }
+
+    /// When the cursor stops hovering on an object, fade out its hovered drawing and highlight over
+    /// this long, instead of switching back to the normal drawing instantly. Pass `None` to
+    /// disable, which is the default.
+    pub fn set_hover_fade(&mut self, duration: Option<Duration>) {
+        self.hover_fade = duration;
+        self.fading = None;
+    }
+
+    /// Activate a modal tool, like drawing or deleting something. While a tool is active, clicking
+    /// fires `WorldOutcome::ToolAction` and dragging fires `WorldOutcome::ToolDragging` and
+    /// `ToolDragEnd`, instead of the normal outcomes. Dragging anywhere goes to the tool, so the
+    /// canvas can't be panned with the mouse, but it can still be zoomed. Objects don't respond to
+    /// hotkeys, but hovering still works. Pass `None` to go back to normal.
+    pub fn set_active_tool(&mut self, tool: Option<&'static str>) {
+        self.active_tool = tool;
+        self.dragging_from = None;
+    }
+
+    /// Returns the tool set by `set_active_tool`, if any.
+    pub fn get_active_tool(&self) -> Option<&'static str> {
+        self.active_tool
+    }
+
+    /// If false, the `World` ignores the user's mouse and keyboard, except for panning and zooming
+    /// the canvas. `event` never returns anything, but hovering can still be controlled with
+    /// `set_hovering`.
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+        if !interactive {
+            self.dragging_from = None;
+        }
+    }
+
+    /// Returns the object currently hovered on, if any.
+    pub fn get_hovering(&self) -> Option<ID> {
+        self.hovering
+    }
+
+    /// Returns the object currently being dragged, if any. Before the cursor moves past the drag
+    /// threshold, the object isn't considered dragged yet.
+    pub fn dragging_object(&self) -> Option<ID> {
+        match self.dragging_from {
+            Some(DragState { moved: true, .. }) => self.hovering,
+            _ => None,
+        }
+    }
+
+    /// When the cursor is over multiple objects with the same zorder, normally the one added last
+    /// is hovered. If this is enabled, the object with the smallest hitbox wins instead, so a small
+    /// handle sitting on a large polygon is easy to select.
+    pub fn set_prefer_smaller_hitboxes(&mut self, prefer: bool) {
+        self.prefer_smaller_hitboxes = prefer;
+    }
+
+    /// Keep the camera from panning beyond these map-space bounds, so users can't get lost in
+    /// empty space. If the screen shows more than the bounds, they're centered. `None` lets the
+    /// camera pan anywhere.
+    pub fn set_pan_bounds(&mut self, bounds: Option<Bounds>) {
+        self.pan_bounds = bounds;
+    }
+
+    /// Is the cursor currently over any object?
+    pub fn is_hovering(&self) -> bool {
+        self.hovering.is_some()
+    }
+
+    /// Draw an object as if the cursor is hovering on it. Unless the `World` isn't interactive,
+    /// this is overwritten as soon as the real cursor moves.
+    pub fn set_hovering(&mut self, id: Option<ID>) {
+        if let Some(id) = id {
+            assert!(
+                self.objects.contains_key(&id),
+                "can't hover on unknown {:?}",
+                id
+            );
         }
+        self.hovering = id;
+    }
+
+    /// Draw a pulsing outline around one object to draw attention to it, such as during a
+    /// tutorial. Pass `None` to stop. This is independent of hovering.
+    pub fn pulse(&mut self, id: Option<ID>) {
+        self.pulsing = id.map(|id| (id, Instant::now()));
+    }
+
+    /// Highlight a set of objects, such as search results, replacing any previous set. This is
+    /// independent of hovering. Pass an empty list to stop.
+    pub fn set_highlighted(&mut self, ids: Vec<ID>) {
+        self.highlighted = ids.into_iter().collect();
+    }
+
+    /// Change how `set_highlighted` draws objects. By default, they're filled in cyan.
+    pub fn set_highlighted_style(&mut self, style: HighlightStyle) {
+        self.highlighted_style = style;
+    }
+
+    /// When labels on different objects overlap, only draw the label for the object on top.
+    pub fn set_declutter_labels(&mut self, declutter: bool) {
+        self.declutter_labels = declutter;
+    }
+
+    /// Draw a crosshair at the cursor, along with its map-space coordinates. Useful for editing
+    /// modes.
+    pub fn show_crosshair(&mut self, show: bool) {
+        self.show_crosshair = show;
+    }
+
+    /// Where the cursor was in map-space during the last call to `event`, if it was on the map.
+    pub fn get_cursor_pt(&self) -> Option<Pt2D> {
+        self.cursor
+    }
+
+    /// During each `event`, center the camera on an object, so it stays in view while it moves.
+    /// Pass `None` to stop. If the user drags the map to pan, following also stops.
+    pub fn follow(&mut self, id: Option<ID>) {
+        self.following = id;
     }
 
     /// After adding all objects to a `World`, call this to initially detect if the cursor is
@@ -298,7 +1041,7 @@ impl<ID: ObjectID> World<ID> {
         self.hovering = ctx
             .canvas
             .get_cursor_in_map_space()
-            .and_then(|cursor| self.calculate_hover(cursor));
+            .and_then(|cursor| self.calculate_hover(cursor, ctx.canvas.cam_zoom));
     }
 
     /// If a drag event causes the world to be totally rebuilt, call this with the previous world
@@ -316,27 +1059,156 @@ impl<ID: ObjectID> World<ID> {
         }
     }
 
+    /// Replace an object's hitbox and drawing in place, such as after its shape is edited. This is
+    /// much cheaper than rebuilding the whole `World`, and keeps hovering and other state intact.
+    /// If `draw_hover` is `None`, the previous hovered drawing is kept; it can be a different type
+    /// than `draw_normal`, so pass something like `None::<GeomBatch>`.
+    pub fn update_object<I: Into<ToggleZoomedBuilder>, H: Into<ToggleZoomedBuilder>>(
+        &mut self,
+        ctx: &EventCtx,
+        id: ID,
+        hitbox: Polygon,
+        draw_normal: I,
+        draw_hover: Option<H>,
+    ) {
+        let obj = self
+            .objects
+            .get_mut(&id)
+            .expect("updating an object that doesn't exist");
+        self.quadtree.remove(obj.quadtree_id).unwrap();
+        obj.quadtree_id = self
+            .quadtree
+            .insert_with_box(id, hitbox.get_bounds().as_bbox());
+        obj.hitbox = hitbox;
+        obj.draw_normal = NormalDrawing::Ready(draw_normal.into().build(ctx));
+        if let Some(draw) = draw_hover {
+            obj.draw_hover = Some(draw.into().build(ctx));
+            obj.hoverable = true;
+        }
+    }
+
     /// Draw something underneath all objects. This is useful for performance, when a large number
     /// of objects never change appearance.
     pub fn draw_master_batch<I: Into<ToggleZoomedBuilder>>(&mut self, ctx: &EventCtx, draw: I) {
         self.draw_master_batches.push(draw.into().build(ctx));
     }
 
+    /// Draw something on top of all objects every frame, like grid lines or a scale bar. These
+    /// can't be hovered on or clicked. Overlays are drawn in the order they're added.
+    pub fn add_overlay_draw(&mut self, draw: Box<dyn Fn(&mut GfxCtx)>) {
+        self.overlay_draws.push(draw);
+    }
+
+    /// One input event can cause several outcomes at once, like the cursor moving onto an object
+    /// and clicking it. Normally only one of them is reported. If the caller does something
+    /// expensive for each outcome, like rebuilding a panel, this makes sure that one is the most
+    /// significant outcome of the event. From most to least significant:
+    ///
+    /// 1. Clicks, keypresses, scrolling, drag ends, and tool actions
+    /// 2. Dragging
+    /// 3. Hover changes, from the object hovered before the event to the one hovered after. If
+    ///    the cursor winds up back where it started, there's no change to report.
+    ///
+    /// Nothing is held back for later events, so less significant outcomes are dropped.
+    pub fn set_coalesce_outcomes(&mut self, coalesce: bool) {
+        self.coalesce_outcomes = coalesce;
+    }
+
     /// Let objects in the world respond to something happening.
     pub fn event(&mut self, ctx: &mut EventCtx) -> WorldOutcome<ID> {
-        if let Some((drag_from, moved)) = self.dragging_from {
+        let prev_hovering = self.hovering;
+        let outcome = self.handle_event(ctx);
+        if let Some(fade) = self.hover_fade {
+            if let Some(prev) = prev_hovering {
+                if self.hovering != Some(prev) {
+                    self.fading = Some((prev, Instant::now()));
+                }
+            }
+            match self.fading {
+                Some((_, started)) if abstutil::elapsed_seconds(started) < fade.inner_seconds() => {
+                    // Keep redrawing until the fade finishes
+                    ctx.request_update(UpdateType::Game);
+                }
+                _ => {
+                    self.fading = None;
+                }
+            }
+        }
+        // The cursor is reset before every event, so set it every time
+        if let Some(cursor) = self
+            .hovering
+            .and_then(|id| self.objects.get(&id))
+            .and_then(|obj| obj.cursor)
+        {
+            ctx.set_cursor_icon(cursor.icon());
+        }
+        if let Some(ref bounds) = self.pan_bounds {
+            clamp_camera(ctx, bounds);
+        }
+        if !self.coalesce_outcomes {
+            return outcome;
+        }
+        // handle_event only reports one outcome, so a hover change can get lost behind something
+        // else
+        let hover = if prev_hovering == self.hovering {
+            WorldOutcome::Nothing
+        } else {
+            WorldOutcome::HoverChanged {
+                from: prev_hovering,
+                to: self.hovering,
+            }
+        };
+        most_significant(vec![outcome, hover])
+    }
+
+    fn handle_event(&mut self, ctx: &mut EventCtx) -> WorldOutcome<ID> {
+        self.cursor = ctx.canvas.get_cursor_in_map_space();
+        if self.pulsing.is_some() || self.num_animated > 0 {
+            // Keep redrawing to animate
+            ctx.request_update(UpdateType::Game);
+        }
+
+        if let Some(id) = self.following {
+            match self.objects.get(&id) {
+                Some(obj) if !ctx.canvas.is_dragging() => {
+                    ctx.canvas.center_on_map_pt(obj.hitbox.center());
+                }
+                _ => {
+                    self.following = None;
+                }
+            }
+        }
+
+        if !self.interactive {
+            ctx.canvas_movement();
+            return WorldOutcome::Nothing;
+        }
+
+        if let Some(tool) = self.active_tool {
+            return self.handle_tool_event(ctx, tool);
+        }
+
+        if let Some(drag) = self.dragging_from {
             if ctx.input.left_mouse_button_released() {
                 self.dragging_from = None;
+                let obj = self.hovering.unwrap();
                 // For objects that're both clickable and draggable, we don't know what the user is
                 // doing until they release the mouse!
-                if !moved && self.objects[&self.hovering.unwrap()].clickable {
-                    return WorldOutcome::ClickedObject(self.hovering.unwrap());
+                if !drag.moved && self.objects[&obj].clickable {
+                    return WorldOutcome::ClickedObject(obj);
                 }
 
                 self.hovering = ctx
                     .canvas
                     .get_cursor_in_map_space()
-                    .and_then(|cursor| self.calculate_hover(cursor));
+                    .and_then(|cursor| self.calculate_hover(cursor, ctx.canvas.cam_zoom));
+                if drag.moved {
+                    return WorldOutcome::DragEnd {
+                        obj,
+                        dx: drag.last.x() - drag.start.x(),
+                        dy: drag.last.y() - drag.start.y(),
+                    };
+                }
                 return WorldOutcome::Nothing;
             }
             // Allow zooming, but not panning, while dragging
@@ -346,9 +1218,24 @@ impl<ID: ObjectID> World<ID> {
 
             if ctx.redo_mouseover() {
                 if let Some(cursor) = ctx.canvas.get_cursor_in_map_space() {
-                    let dx = cursor.x() - drag_from.x();
-                    let dy = cursor.y() - drag_from.y();
-                    self.dragging_from = Some((cursor, true));
+                    if !drag.moved {
+                        let threshold = self.objects[&self.hovering.unwrap()]
+                            .drag_threshold
+                            .unwrap_or(self.drag_threshold);
+                        // Convert from map-space to screen-space
+                        if drag.start.dist_to(cursor).inner_meters() * ctx.canvas.cam_zoom
+                            < threshold
+                        {
+                            return WorldOutcome::Nothing;
+                        }
+                    }
+                    let dx = cursor.x() - drag.last.x();
+                    let dy = cursor.y() - drag.last.y();
+                    self.dragging_from = Some(DragState {
+                        start: drag.start,
+                        last: cursor,
+                        moved: true,
+                    });
                     return WorldOutcome::Dragging {
                         obj: self.hovering.unwrap(),
                         dx,
@@ -364,13 +1251,23 @@ impl<ID: ObjectID> World<ID> {
         let cursor = if let Some(pt) = ctx.canvas.get_cursor_in_map_space() {
             pt
         } else {
-            self.hovering = None;
+            if let Some(from) = self.hovering.take() {
+                return WorldOutcome::HoverChanged {
+                    from: Some(from),
+                    to: None,
+                };
+            }
             return WorldOutcome::Nothing;
         };
 
         // Possibly recalculate hovering
+        let mut hover_changed_from = None;
         if ctx.redo_mouseover() {
-            self.hovering = self.calculate_hover(cursor);
+            let prev = self.hovering;
+            self.hovering = self.calculate_hover(cursor, ctx.canvas.cam_zoom);
+            if prev != self.hovering {
+                hover_changed_from = Some(prev);
+            }
         }
 
         // If we're hovering on a draggable thing, only allow zooming, not panning
@@ -387,16 +1284,28 @@ impl<ID: ObjectID> World<ID> {
             if obj.draggable {
                 allow_panning = false;
                 if ctx.input.left_mouse_button_pressed() {
-                    self.dragging_from = Some((cursor, false));
+                    self.dragging_from = Some(DragState {
+                        start: cursor,
+                        last: cursor,
+                        moved: false,
+                    });
                     return WorldOutcome::Nothing;
                 }
             }
 
-            for (key, action) in &obj.keybindings {
+            for (key, action) in obj.keybindings.iter().chain(self.keybindings.iter()) {
                 if ctx.input.pressed(key.clone()) {
                     return WorldOutcome::Keypress(action, id);
                 }
             }
+
+            if let Some(key) = self.scroll_modifier {
+                if ctx.is_key_down(key) {
+                    if let Some((_, dy)) = ctx.input.get_mouse_scroll() {
+                        return WorldOutcome::Scrolled(id, dy);
+                    }
+                }
+            }
         }
 
         if allow_panning {
@@ -411,30 +1320,231 @@ impl<ID: ObjectID> World<ID> {
             }
         }
 
+        // Report this last, so that the cursor moving doesn't interfere with anything else
+        if let Some(from) = hover_changed_from {
+            return WorldOutcome::HoverChanged {
+                from,
+                to: self.hovering,
+            };
+        }
+        WorldOutcome::Nothing
+    }
+
+    /// Like `handle_event`, but clicks and drags all go to the active tool
+    fn handle_tool_event(&mut self, ctx: &mut EventCtx, tool: &'static str) -> WorldOutcome<ID> {
+        if let Some(drag) = self.dragging_from {
+            if ctx.input.left_mouse_button_released() {
+                self.dragging_from = None;
+                let obj = self.hovering;
+                self.hovering = ctx
+                    .canvas
+                    .get_cursor_in_map_space()
+                    .and_then(|cursor| self.calculate_hover(cursor, ctx.canvas.cam_zoom));
+                if drag.moved {
+                    return WorldOutcome::ToolDragEnd {
+                        tool,
+                        obj,
+                        dx: drag.last.x() - drag.start.x(),
+                        dy: drag.last.y() - drag.start.y(),
+                    };
+                }
+                return WorldOutcome::ToolAction {
+                    tool,
+                    obj,
+                    pt: drag.start,
+                };
+            }
+            // Allow zooming, but not panning, while dragging
+            if let Some((_, dy)) = ctx.input.get_mouse_scroll() {
+                ctx.canvas.zoom(dy, ctx.canvas.get_cursor());
+            }
+
+            if ctx.redo_mouseover() {
+                if let Some(cursor) = ctx.canvas.get_cursor_in_map_space() {
+                    // Convert from map-space to screen-space
+                    if !drag.moved
+                        && drag.start.dist_to(cursor).inner_meters() * ctx.canvas.cam_zoom
+                            < self.drag_threshold
+                    {
+                        return WorldOutcome::Nothing;
+                    }
+                    self.dragging_from = Some(DragState {
+                        start: drag.start,
+                        last: cursor,
+                        moved: true,
+                    });
+                    return WorldOutcome::ToolDragging {
+                        tool,
+                        obj: self.hovering,
+                        dx: cursor.x() - drag.last.x(),
+                        dy: cursor.y() - drag.last.y(),
+                        cursor,
+                    };
+                }
+            }
+            return WorldOutcome::Nothing;
+        }
+
+        let cursor = if let Some(pt) = ctx.canvas.get_cursor_in_map_space() {
+            pt
+        } else {
+            if let Some(from) = self.hovering.take() {
+                return WorldOutcome::HoverChanged {
+                    from: Some(from),
+                    to: None,
+                };
+            }
+            return WorldOutcome::Nothing;
+        };
+
+        let mut hover_changed_from = None;
+        if ctx.redo_mouseover() {
+            let prev = self.hovering;
+            self.hovering = self.calculate_hover(cursor, ctx.canvas.cam_zoom);
+            if prev != self.hovering {
+                hover_changed_from = Some(prev);
+            }
+        }
+
+        // Start a drag before the canvas can, so the tool gets it. If the mouse is released
+        // without moving, it's a click.
+        if ctx.input.left_mouse_button_pressed() {
+            self.dragging_from = Some(DragState {
+                start: cursor,
+                last: cursor,
+                moved: false,
+            });
+            return WorldOutcome::Nothing;
+        }
+        ctx.canvas_movement();
+
+        if let Some(from) = hover_changed_from {
+            return WorldOutcome::HoverChanged {
+                from,
+                to: self.hovering,
+            };
+        }
         WorldOutcome::Nothing
     }
 
-    fn calculate_hover(&self, cursor: Pt2D) -> Option<ID> {
+    fn calculate_hover(&self, cursor: Pt2D, cam_zoom: f64) -> Option<ID> {
         let mut objects = Vec::new();
         for &(id, _, _) in &self.quadtree.query(
             // Maybe worth tuning. Since we do contains_pt below, it doesn't matter if this is too
             // big; just a performance impact possibly.
-            Circle::new(cursor, Distance::meters(3.0))
-                .get_bounds()
-                .as_bbox(),
+            Circle::new(
+                cursor,
+                Distance::meters(3.0) + Distance::meters(self.max_hitbox_inflation / cam_zoom),
+            )
+            .get_bounds()
+            .as_bbox(),
         ) {
             objects.push(*id);
         }
-        objects.sort_by_key(|id| self.objects[id].zorder);
+        self.sort_by_draw_order(&mut objects);
         objects.reverse();
 
-        for id in objects {
-            let obj = &self.objects[&id];
-            if obj.draw_hover.is_some() && obj.hitbox.contains_pt(cursor) {
-                return Some(id);
+        let mut hits = objects.into_iter().filter(|id| {
+            let obj = &self.objects[id];
+            obj.hoverable
+                && obj.hit_testable
+                && self.is_visible(obj)
+                && self.hits(obj, cursor, cam_zoom)
+        });
+        let top = hits.next()?;
+        if !self.prefer_smaller_hitboxes {
+            return Some(top);
+        }
+        // Only consider the other hits tied with the top zorder
+        let zorder = self.objects[&top].zorder;
+        let mut best = (top, self.objects[&top].hitbox.area());
+        for id in hits.take_while(|id| self.objects[id].zorder == zorder) {
+            let area = self.objects[&id].hitbox.area();
+            if area < best.1 {
+                best = (id, area);
             }
         }
-        None
+        Some(best.0)
+    }
+
+    // If an object's hover is fading out, how opaque should it be?
+    fn fade_alpha(&self, id: ID) -> Option<f32> {
+        let (fading, started) = self.fading?;
+        if fading != id || self.hovering == Some(id) {
+            return None;
+        }
+        let pct = abstutil::elapsed_seconds(started) / self.hover_fade?.inner_seconds();
+        if pct >= 1.0 {
+            return None;
+        }
+        Some((1.0 - pct) as f32)
+    }
+
+    // Objects are drawn by zorder, then in the order they were added. The quadtree returns objects
+    // in an arbitrary order, so without the tie-breaker, overlapping objects could flicker.
+    fn sort_by_draw_order(&self, objects: &mut [ID]) {
+        objects.sort_by_key(|id| {
+            let obj = &self.objects[id];
+            (obj.zorder, obj.order)
+        });
+    }
+
+    // Does the point fall inside the object's hitbox, accounting for any inflation?
+    fn hits(&self, obj: &Object<ID>, pt: Pt2D, cam_zoom: f64) -> bool {
+        if obj.hitbox.contains_pt(pt) {
+            return true;
+        }
+        let pixels = obj.hitbox_inflation.unwrap_or(self.hitbox_inflation);
+        if pixels <= 0.0 {
+            return false;
+        }
+        // Convert from screen-space to map-space
+        obj.hitbox
+            .intersects(&Circle::new(pt, Distance::meters(pixels / cam_zoom)).to_polygon())
+    }
+
+    // Labels stay the same size on the screen, no matter the zoom. Objects with a higher zorder get
+    // priority when decluttering.
+    fn draw_labels(&self, g: &mut GfxCtx, objects: &[ID]) {
+        let scale = 1.0 / g.canvas.cam_zoom;
+        let mut placed: Vec<Bounds> = Vec::new();
+        let mut batch = GeomBatch::new();
+        for id in objects.iter().rev() {
+            let obj = &self.objects[id];
+            let label = match obj.label {
+                Some(ref label) if self.is_visible(obj) => label,
+                _ => continue,
+            };
+            let label = label.clone().scale(scale).centered_on(obj.hitbox.center());
+            if self.declutter_labels {
+                let bounds = label.get_bounds();
+                if placed.iter().any(|other| {
+                    bounds.min_x < other.max_x
+                        && other.min_x < bounds.max_x
+                        && bounds.min_y < other.max_y
+                        && other.min_y < bounds.max_y
+                }) {
+                    continue;
+                }
+                placed.push(bounds);
+            }
+            batch.append(label);
+        }
+        batch.draw(g);
+    }
+
+    /// Returns all visible objects whose hitbox intersects a rectangle, such as one drawn by the
+    /// user to select many things at once.
+    pub fn objects_in_rect(&self, rect: Bounds) -> Vec<ID> {
+        let rect_poly = rect.get_rectangle();
+        let mut objects = Vec::new();
+        for &(id, _, _) in &self.quadtree.query(rect.as_bbox()) {
+            let obj = &self.objects[id];
+            if self.is_visible(obj) && obj.hitbox.intersects(&rect_poly) {
+                objects.push(*id);
+            }
+        }
+        objects
     }
 
     /// Draw objects in the world that're currently visible.
@@ -448,24 +1558,181 @@ impl<ID: ObjectID> World<ID> {
         for &(id, _, _) in &self.quadtree.query(g.get_screen_bounds().as_bbox()) {
             objects.push(*id);
         }
-        objects.sort_by_key(|id| self.objects[id].zorder);
+        self.sort_by_draw_order(&mut objects);
 
-        for id in objects {
+        let mut drew_tooltip = false;
+        for id in objects.iter().cloned() {
             let mut drawn = false;
             let obj = &self.objects[&id];
+            if !self.is_visible(obj) {
+                continue;
+            }
             if Some(id) == self.hovering {
                 if let Some(ref draw) = obj.draw_hover {
                     draw.draw(g);
                     drawn = true;
                 }
+                if let Some(style) = obj
+                    .highlight_style
+                    .as_ref()
+                    .or_else(|| self.highlight_style.as_ref())
+                {
+                    style.render(&obj.hitbox).draw(g);
+                }
                 if let Some(ref txt) = obj.tooltip {
                     g.draw_mouse_tooltip(txt.clone());
+                    drew_tooltip = true;
                 }
             }
             if !drawn {
-                obj.draw_normal.draw(g);
+                obj.draw_normal.draw(g, 1.0);
             }
+            if let Some(alpha) = self.fade_alpha(id) {
+                if let Some(ref draw) = obj.draw_hover {
+                    draw.draw_with_alpha(g, alpha);
+                }
+                if let Some(style) = obj
+                    .highlight_style
+                    .as_ref()
+                    .or_else(|| self.highlight_style.as_ref())
+                {
+                    let draw = g.upload(style.render(&obj.hitbox));
+                    g.redraw_with_alpha(&draw, alpha);
+                }
+            }
+        }
+
+        if let (Some(alpha), Some(id)) = (self.drag_ghost_alpha, self.dragging_object()) {
+            let drag = self.dragging_from.unwrap();
+            let dx = drag.last.x() - drag.start.x();
+            let dy = drag.last.y() - drag.start.y();
+            // Shift the camera the opposite way to translate without re-uploading anything
+            let zoom = g.canvas.cam_zoom;
+            g.fork(
+                Pt2D::new(g.canvas.cam_x / zoom - dx, g.canvas.cam_y / zoom - dy),
+                ScreenPt::new(0.0, 0.0),
+                zoom,
+                Some(MAPSPACE_Z),
+            );
+            self.objects[&id].draw_normal.draw(g, alpha);
+            g.unfork();
         }
+
+        let mut dashes = GeomBatch::new();
+        let elapsed = abstutil::elapsed_seconds(self.animation_start);
+        for id in &objects {
+            let obj = &self.objects[id];
+            if let Some(dashed) = obj.dashed_outline {
+                if self.is_visible(obj) {
+                    dashes.append(dashed.render(&obj.hitbox, elapsed));
+                }
+            }
+        }
+        dashes.draw(g);
+
+        if !self.highlighted.is_empty() {
+            let mut batch = GeomBatch::new();
+            for id in &objects {
+                let obj = &self.objects[id];
+                if self.highlighted.contains(id) && self.is_visible(obj) {
+                    batch.append(self.highlighted_style.render(&obj.hitbox));
+                }
+            }
+            batch.draw(g);
+        }
+
+        if let Some((id, started)) = self.pulsing {
+            if let Some(obj) = self.objects.get(&id) {
+                // Fade in and out once per second
+                let pct = (abstutil::elapsed_seconds(started) * 2.0 * std::f64::consts::PI).sin();
+                let alpha = 0.6 + 0.4 * pct;
+                HighlightStyle::outline(
+                    Color::YELLOW.alpha(alpha as f32),
+                    Distance::meters(5.0 / g.canvas.cam_zoom),
+                )
+                .render(&obj.hitbox)
+                .draw(g);
+            }
+        }
+
+        // Like ToggleZoomed, only show labels when zoomed in
+        if g.canvas.cam_zoom >= g.canvas.settings.min_zoom_for_detail {
+            self.draw_labels(g, &objects);
+        }
+
+        for draw in &self.overlay_draws {
+            draw(g);
+        }
+
+        if self.show_crosshair {
+            if let Some(pt) = g.canvas.get_cursor_in_map_space() {
+                // Keep the crosshair the same size on the screen, no matter the zoom
+                let length = Distance::meters(30.0 / g.canvas.cam_zoom);
+                let thickness = Distance::meters(2.0 / g.canvas.cam_zoom);
+                let mut batch = GeomBatch::new();
+                batch.push(
+                    Color::RED,
+                    Polygon::rectangle_centered(pt, length, thickness),
+                );
+                batch.push(
+                    Color::RED,
+                    Polygon::rectangle_centered(pt, thickness, length),
+                );
+                batch.draw(g);
+                // Don't cover up an object's tooltip
+                if !drew_tooltip {
+                    g.draw_mouse_tooltip(Text::from(format!("({:.1}, {:.1})", pt.x(), pt.y())));
+                }
+            }
+        }
+    }
+}
+
+/// For `set_coalesce_outcomes`, pick the most significant outcome. Ties go to the earlier one.
+fn most_significant<ID: ObjectID>(outcomes: Vec<WorldOutcome<ID>>) -> WorldOutcome<ID> {
+    let mut best = WorldOutcome::Nothing;
+    for outcome in outcomes {
+        if significance(&outcome) > significance(&best) {
+            best = outcome;
+        }
+    }
+    best
+}
+
+fn significance<ID: ObjectID>(outcome: &WorldOutcome<ID>) -> usize {
+    match outcome {
+        WorldOutcome::Nothing => 0,
+        WorldOutcome::HoverChanged { .. } => 1,
+        WorldOutcome::Dragging { .. } | WorldOutcome::ToolDragging { .. } => 2,
+        _ => 3,
+    }
+}
+
+fn clamp_camera(ctx: &mut EventCtx, bounds: &Bounds) {
+    // Clamp one axis, given the visible range in map-space
+    fn clamp(center: f64, visible: f64, min: f64, max: f64) -> f64 {
+        if visible >= max - min {
+            (min + max) / 2.0
+        } else {
+            center.max(min + visible / 2.0).min(max - visible / 2.0)
+        }
+    }
+
+    let center = ctx.canvas.center_to_map_pt();
+    let x = clamp(
+        center.x(),
+        ctx.canvas.window_width / ctx.canvas.cam_zoom,
+        bounds.min_x,
+        bounds.max_x,
+    );
+    let y = clamp(
+        center.y(),
+        ctx.canvas.window_height / ctx.canvas.cam_zoom,
+        bounds.min_y,
+        bounds.max_y,
+    );
+    if x != center.x() || y != center.y() {
+        ctx.canvas.center_on_map_pt(Pt2D::new(x, y));
     }
 }
 
@@ -483,3 +1750,99 @@ impl World<DummyID> {
         self.add(DummyID(self.objects.len()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Like ObjectBuilder::build, but tests have no EventCtx to upload drawings. The object can
+    // still be hovered on.
+    fn build_without_drawing(mut obj: ObjectBuilder<DummyID>) {
+        obj.apply_translation();
+        let hitbox = obj.hitbox.take().expect("didn't specify hitbox");
+        let draw_normal = NormalDrawing::Lazy {
+            make: Box::new(ToggleZoomed::builder),
+            cached: RefCell::new(None),
+        };
+        obj.insert_object(hitbox, draw_normal, None, true, None);
+    }
+
+    #[test]
+    fn test_translate_moves_hitbox_with_drawing() {
+        let mut world: World<DummyID> = World::unbounded();
+        let square = Polygon::rectangle(10.0, 10.0);
+        let mut obj = world
+            .add_unnamed()
+            .hitbox(square.clone())
+            .draw(GeomBatch::from(vec![(Color::RED, square)]))
+            .translate(100.0, 50.0);
+        obj.apply_translation();
+
+        let hitbox = obj.hitbox.as_ref().unwrap();
+        assert!(hitbox.contains_pt(Pt2D::new(105.0, 55.0)));
+        assert!(!hitbox.contains_pt(Pt2D::new(5.0, 5.0)));
+        assert_eq!(
+            hitbox.get_bounds(),
+            obj.draw_normal.as_ref().unwrap().unzoomed.get_bounds()
+        );
+
+        build_without_drawing(obj);
+        assert_eq!(
+            world.calculate_hover(Pt2D::new(105.0, 55.0), 1.0),
+            Some(DummyID(0))
+        );
+        assert_eq!(world.calculate_hover(Pt2D::new(5.0, 5.0), 1.0), None);
+    }
+
+    #[test]
+    fn test_coalesce_click_beats_hover() {
+        // In one event, the cursor moves onto an object and clicks it
+        let hover = || WorldOutcome::HoverChanged {
+            from: None,
+            to: Some(DummyID(0)),
+        };
+        assert!(matches!(
+            most_significant(vec![WorldOutcome::ClickedObject(DummyID(0)), hover()]),
+            WorldOutcome::ClickedObject(DummyID(0))
+        ));
+        assert!(matches!(
+            most_significant(vec![hover(), WorldOutcome::ClickedObject(DummyID(0))]),
+            WorldOutcome::ClickedObject(DummyID(0))
+        ));
+
+        let drag = WorldOutcome::Dragging {
+            obj: DummyID(0),
+            dx: 1.0,
+            dy: 2.0,
+            cursor: Pt2D::new(1.0, 2.0),
+        };
+        assert!(matches!(
+            most_significant(vec![hover(), drag]),
+            WorldOutcome::Dragging { .. }
+        ));
+        assert!(matches!(
+            most_significant(vec![WorldOutcome::Nothing, hover()]),
+            WorldOutcome::HoverChanged { .. }
+        ));
+    }
+
+    #[test]
+    fn test_objects_ordered_by_registration() {
+        let mut world: World<DummyID> = World::unbounded();
+        let square = Polygon::rectangle(10.0, 10.0);
+        for id in 0..2 {
+            build_without_drawing(
+                world
+                    .add(DummyID(id))
+                    .hitbox(square.clone())
+                    .draw(GeomBatch::from(vec![(Color::RED, square.clone())])),
+            );
+        }
+        assert!(world.objects[&DummyID(0)].order < world.objects[&DummyID(1)].order);
+        // With equal zorder, the object added later is drawn on top, so it wins hovering
+        assert_eq!(
+            world.calculate_hover(Pt2D::new(5.0, 5.0), 1.0),
+            Some(DummyID(1))
+        );
+    }
+}